@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FilePaths,
+    generation_emission_match::{Match, fuel_co2_intensity},
+    load_csv_file,
+};
+
+/// Ambient and district heating supply temperature in K, for the exergy of heat
+const AMBIENT_TEMPERATURE: f64 = 288.15;
+const SUPPLY_TEMPERATURE: f64 = 363.15;
+
+/// How the emissions of combined heat and power plants are split between heat and electricity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum AllocationMethod {
+    /// by fuel input, estimated from the heat and electricity output and assumed efficiencies
+    #[default]
+    Efficiency,
+    /// by energy content of the heat and electricity output
+    Energy,
+    /// by exergy (work potential) of the heat and electricity output
+    Exergy,
+    /// heat gets the emissions of a reference boiler producing it, electricity the rest
+    Substitution,
+    /// by fuel input that separate heat and electricity production would need, using the EU
+    /// harmonised reference efficiencies ("alternative generation" / PEF)
+    Pef,
+}
+
+impl AllocationMethod {
+    pub(crate) fn parse(method: &str) -> Result<Self, String> {
+        match method {
+            "efficiency" => Ok(Self::Efficiency),
+            "energy" => Ok(Self::Energy),
+            "exergy" => Ok(Self::Exergy),
+            "substitution" => Ok(Self::Substitution),
+            "pef" => Ok(Self::Pef),
+            _ => Err(format!("unknown allocation method {method}")),
+        }
+    }
+
+    /// Share of a plant's emissions that is caused by generating the given amount of heat. The
+    /// given efficiencies are only used by the efficiency method.
+    pub(crate) fn heat_share(
+        self,
+        m: &Match,
+        generation_heat: f64,
+        efficiency_heat: f64,
+        efficiency_el: f64,
+    ) -> f64 {
+        let fuel = m.fuel.as_deref().unwrap();
+        let (heat, el) = match self {
+            Self::Efficiency => {
+                (generation_heat / efficiency_heat, m.generation_el / efficiency_el)
+            }
+            Self::Energy => (generation_heat, m.generation_el),
+            Self::Exergy => {
+                let carnot_factor = 1.0 - AMBIENT_TEMPERATURE / SUPPLY_TEMPERATURE;
+                (generation_heat * carnot_factor, m.generation_el)
+            }
+            Self::Substitution => {
+                let (reference_heat, _) = reference_efficiencies(fuel);
+                // t CO2 per TJ -> t CO2 per MWh
+                let intensity = fuel_co2_intensity(fuel).unwrap() * 0.0036;
+                let emissions_heat = generation_heat / reference_heat * intensity;
+                let emission_sum: f64 = m.emission.iter().map(|e| e.emissions).sum();
+                return (emissions_heat / emission_sum).min(1.0);
+            }
+            Self::Pef => {
+                let (reference_heat, reference_el) = reference_efficiencies(fuel);
+                (generation_heat / reference_heat, m.generation_el / reference_el)
+            }
+        };
+
+        heat / (heat + el)
+    }
+}
+
+/// Harmonised efficiency reference values for separate production of heat (hot water) and
+/// electricity, Commission Delegated Regulation (EU) 2015/2402, Annexes I and II
+fn reference_efficiencies(fuel: &str) -> (f64, f64) {
+    match fuel {
+        "gas" => (0.92, 0.53),
+        "coal" => (0.88, 0.442),
+        "lignite" => (0.86, 0.418),
+        "oil" => (0.89, 0.442),
+        _ => panic!("no reference efficiencies for fuel {fuel}"),
+    }
+}
+
+#[derive(Deserialize)]
+struct EfficienciesRow {
+    fuel: String,
+    country: String,
+    efficiency_heat: f64,
+    efficiency_el: f64,
+}
+
+/// Assumed efficiencies of heat and electricity generation for the efficiency method, per fuel
+/// and optionally per country, from "data/efficiencies.csv"
+pub(crate) struct Efficiencies {
+    /// (fuel, country) -> (heat, electricity), country is empty for fuel defaults
+    map: BTreeMap<(String, String), (f64, f64)>,
+}
+
+impl Efficiencies {
+    pub(crate) fn load(paths: &FilePaths) -> Self {
+        let mut map = BTreeMap::new();
+        for result in load_csv_file(&paths.efficiencies_file(), ',').deserialize() {
+            let row: EfficienciesRow = result.unwrap();
+            map.insert((row.fuel, row.country), (row.efficiency_heat, row.efficiency_el));
+        }
+
+        Self { map }
+    }
+
+    /// Efficiencies of heat and electricity generation, the country's if listed, otherwise the
+    /// fuel's defaults
+    pub(crate) fn get(&self, fuel: &str, country: &str) -> (f64, f64) {
+        let country_specific = self.map.get(&(fuel.to_string(), country.to_string()));
+        *country_specific
+            .or_else(|| self.map.get(&(fuel.to_string(), String::new())))
+            .unwrap_or_else(|| panic!("no efficiencies for fuel {fuel} in efficiencies.csv"))
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{
+    FilePaths,
+    generation_emission_match::{YearlyGeneration, fuel_co2_intensity},
+    load_csv_file,
+};
+
+/// Default CO2 emission factor of wood / wood waste, in t CO2 per TJ of fuel input (2006 IPCC
+/// Guidelines, Vol. 2, Table 2.2)
+const BIOMASS_CO2_INTENSITY: f64 = 112.0;
+
+#[derive(Deserialize)]
+struct BiomassShareRow {
+    eic: String,
+    biomass_share: f64,
+}
+
+/// Shares of biomass in the fuel input of co-fired generation units, from the optional
+/// "data/[year]/biomass_shares.csv"
+pub(crate) struct BiomassShares {
+    /// EIC -> share of the fuel input (energy)
+    map: BTreeMap<String, f64>,
+}
+
+impl BiomassShares {
+    pub(crate) fn load(paths: &FilePaths) -> Self {
+        let mut map = BTreeMap::new();
+        let shares_file = paths.biomass_shares_file();
+        if shares_file.exists() {
+            for result in load_csv_file(&shares_file, ',').deserialize() {
+                let row: BiomassShareRow = result.unwrap();
+                assert!(
+                    (0.0..1.0).contains(&row.biomass_share),
+                    "bad biomass share of {} in {shares_file:?}, must be at least 0 and below 1",
+                    row.eic
+                );
+                map.insert(row.eic, row.biomass_share);
+            }
+        }
+
+        Self { map }
+    }
+
+    /// Biomass share of the fuel input of the given generation units, weighted by their output.
+    /// Units that are not listed don't use biomass.
+    pub(crate) fn get(&self, generation: &[YearlyGeneration]) -> f64 {
+        let output_sum: f64 = generation.iter().map(|g| g.output).sum();
+        if output_sum == 0.0 {
+            return 0.0;
+        }
+
+        let biomass_output: f64 = generation
+            .iter()
+            .map(|g| g.output * self.map.get(&g.eic).copied().unwrap_or_default())
+            .sum();
+        biomass_output / output_sum
+    }
+}
+
+/// Biogenic CO2 emitted along with the given fossil emissions, if the given share of the fuel
+/// input is biomass. ETS verified emissions don't include it.
+pub(crate) fn biogenic_emissions(fossil_emissions: f64, fuel: &str, cofiring_share: f64) -> f64 {
+    if cofiring_share == 0.0 {
+        return 0.0;
+    }
+
+    let fossil_intensity =
+        fuel_co2_intensity(fuel).unwrap_or_else(|| panic!("co-firing unsupported for fuel {fuel}"));
+    let biomass_input =
+        fossil_emissions / fossil_intensity * cofiring_share / (1.0 - cofiring_share);
+    biomass_input * BIOMASS_CO2_INTENSITY
+}
@@ -0,0 +1,54 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::{FilePaths, load_csv_file};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Dataset {
+    /// ETS registry codes (verified emissions)
+    Ets,
+    /// Entso-E map codes (unit generation)
+    Entsoe,
+    /// Eurostat geo codes (degree days)
+    Eurostat,
+}
+
+#[derive(Deserialize)]
+struct CountryCodeRow {
+    dataset: Dataset,
+    code: String,
+    country: String,
+}
+
+/// Maps country codes of the different data sources to the codes used in all outputs, based on
+/// "data/country_codes.csv". Codes that are not listed there are used as they are.
+pub(crate) struct CountryCodes {
+    map: BTreeMap<(Dataset, String), String>,
+}
+
+impl CountryCodes {
+    pub(crate) fn load(paths: &FilePaths) -> Self {
+        let mut map = BTreeMap::new();
+        for result in load_csv_file(&paths.country_codes_file(), ',').deserialize() {
+            let row: CountryCodeRow = result.unwrap();
+            map.insert((row.dataset, row.code), row.country);
+        }
+
+        Self { map }
+    }
+
+    pub(crate) fn normalize(&self, dataset: Dataset, code: &str) -> String {
+        self.map.get(&(dataset, code.to_string())).map_or(code, String::as_str).to_string()
+    }
+}
+
+/// Prints (normalized) country codes of a data set that have no counterpart in the other data sets,
+/// which might point to a missing entry in "data/country_codes.csv".
+pub(crate) fn report_unmapped(dataset: Dataset, codes: &BTreeSet<String>) {
+    if !codes.is_empty() {
+        let codes = codes.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+        println!("Info: Unmapped {dataset:?} country codes: {codes}");
+    }
+}
@@ -0,0 +1,67 @@
+use std::ops::RangeInclusive;
+
+use serde::Deserialize;
+
+use crate::{FilePaths, load_csv_file};
+
+/// Entso-E publishes generation per unit from 2015 on
+const FIRST_YEAR: u32 = 2015;
+/// Earlier years use the ETS phase III allocation rules, see README.md
+const FIRST_YEAR_WITHOUT_WARNING: u32 = 2020;
+
+#[derive(Deserialize)]
+struct EtsParametersRow {
+    year: u32,
+    beta: f64,
+    gamma: f64,
+    heat_benchmark: f64,
+    baseline_from: u32,
+    baseline_to: u32,
+}
+
+/// Free allocation parameters of one year, from "data/ets_parameters.csv". See README.md
+pub(crate) struct EtsParameters {
+    /// reduction factor applied to all heat allocations
+    pub(crate) beta: f64,
+    /// carbon leakage exposure factor, applied to non-privileged heat allocations only
+    pub(crate) gamma: f64,
+    /// t CO2 per TJ heat
+    pub(crate) heat_benchmark: f64,
+    /// years whose heat output the allocations are based on
+    pub(crate) baseline: RangeInclusive<u32>,
+}
+
+impl EtsParameters {
+    pub(crate) fn load(paths: &FilePaths, year: u32) -> Self {
+        let parameters_file = paths.ets_parameters_file();
+        let row = load_csv_file(&parameters_file, ',')
+            .deserialize()
+            .map(|result| -> EtsParametersRow { result.unwrap() })
+            .find(|row| row.year == year)
+            .unwrap_or_else(|| panic!("year {year} is unsupported, add it to {parameters_file:?}"));
+
+        Self {
+            beta: row.beta,
+            gamma: row.gamma,
+            heat_benchmark: row.heat_benchmark,
+            baseline: row.baseline_from..=row.baseline_to,
+        }
+    }
+}
+
+/// Rejects years without Entso-E generation per unit and warns about the limitations of ETS phase
+/// III years
+pub(crate) fn check_year(year: u32) {
+    assert!(
+        year >= FIRST_YEAR,
+        "year {year} is unsupported, Entso-E generation per unit is only available from \
+         {FIRST_YEAR} on"
+    );
+    if year < FIRST_YEAR_WITHOUT_WARNING {
+        println!(
+            "Warning: {year} uses ETS phase III allocation rules. Sigma is still estimated from \
+             the 2018 and 2019 allocations, and free allocations for electricity (Article 10c) \
+             inflate the heat output in some countries."
+        );
+    }
+}
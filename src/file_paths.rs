@@ -17,10 +17,26 @@ impl FilePaths {
         Self { data_dir, year_dir, year }
     }
 
+    pub(crate) fn year(&self) -> u32 {
+        self.year
+    }
+
+    pub(crate) fn biomass_shares_file(&self) -> PathBuf {
+        self.year_dir.join("biomass_shares.csv")
+    }
+
+    pub(crate) fn country_codes_file(&self) -> PathBuf {
+        self.data_dir.join("country_codes.csv")
+    }
+
     pub(crate) fn degree_days_file(&self) -> PathBuf {
         self.data_dir.join("degree_days/nrg_chdd_a.tsv")
     }
 
+    pub(crate) fn efficiencies_file(&self) -> PathBuf {
+        self.data_dir.join("efficiencies.csv")
+    }
+
     pub(crate) fn emissions_file(&self) -> PathBuf {
         self.year_dir.join("preprocessed/powerplant_emissions.csv")
     }
@@ -45,6 +61,14 @@ impl FilePaths {
         panic!("File not found: {path:?}");
     }
 
+    pub(crate) fn ets_parameters_file(&self) -> PathBuf {
+        self.data_dir.join("ets_parameters.csv")
+    }
+
+    pub(crate) fn fuel_factors_file(&self) -> PathBuf {
+        self.data_dir.join("fuel_factors.csv")
+    }
+
     pub(crate) fn generation_file(&self) -> PathBuf {
         self.year_dir.join("preprocessed/powerplant_generation.csv")
     }
@@ -53,10 +77,30 @@ impl FilePaths {
         self.year_dir.join("manual_matches.csv")
     }
 
+    pub(crate) fn shared_manual_matches_file(&self) -> PathBuf {
+        self.data_dir.join("manual_matches.csv")
+    }
+
+    pub(crate) fn opsd_plants_file(&self) -> PathBuf {
+        self.data_dir.join("opsd/conventional_power_plants_DE.csv")
+    }
+
+    pub(crate) fn plant_registry_file(&self) -> PathBuf {
+        self.data_dir.join("plant_registry.csv")
+    }
+
+    pub(crate) fn stop_words_file(&self) -> PathBuf {
+        self.data_dir.join("stop_words.csv")
+    }
+
     pub(crate) fn out_powerplants_file(&self) -> PathBuf {
         self.year_dir.join("output/powerplants.csv")
     }
 
+    pub(crate) fn previous_year_powerplants_file(&self) -> PathBuf {
+        self.data_dir.join(format!("{}/output/powerplants.csv", self.year - 1))
+    }
+
     pub(crate) fn ignored_powerplants_file(&self) -> PathBuf {
         self.year_dir.join("output/ignored_powerplants.csv")
     }
@@ -65,6 +109,26 @@ impl FilePaths {
         self.year_dir.join("output/countries.csv")
     }
 
+    pub(crate) fn out_unmatched_emissions_file(&self) -> PathBuf {
+        self.year_dir.join("output/unmatched_emissions.csv")
+    }
+
+    pub(crate) fn out_ets_coverage_file(&self) -> PathBuf {
+        self.year_dir.join("output/ets_coverage.csv")
+    }
+
+    pub(crate) fn out_sensitivity_file(&self) -> PathBuf {
+        self.year_dir.join("output/sensitivity.csv")
+    }
+
+    pub(crate) fn out_worklist_file(&self) -> PathBuf {
+        self.year_dir.join("output/worklist.csv")
+    }
+
+    pub(crate) fn uncertainty_file(&self) -> PathBuf {
+        self.data_dir.join("uncertainty.csv")
+    }
+
     pub(crate) fn verified_emissions_file(&self) -> PathBuf {
         self.data_dir.join("verified_ets_emissions/verified_emissions.xlsx")
     }
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{FilePaths, generation_emission_match::fuel_co2_intensity, load_csv_file};
+
+/// 100-year global warming potentials, IPCC Fifth Assessment Report (AR5)
+const GWP_CH4: f64 = 28.0;
+const GWP_N2O: f64 = 265.0;
+
+#[derive(Deserialize)]
+struct FuelFactorsRow {
+    fuel: String,
+    /// kg CH4 per TJ fuel input
+    ch4: f64,
+    /// kg N2O per TJ fuel input
+    n2o: f64,
+    /// t CO2eq per TJ fuel input, no defaults are provided
+    upstream: Option<f64>,
+}
+
+/// Emissions besides the direct CO2 of the ETS data, per fuel, from "data/fuel_factors.csv"
+pub(crate) struct FuelFactors {
+    /// fuel -> (t CO2eq of CH4 and N2O from combustion, t CO2eq upstream), both per TJ fuel input
+    map: BTreeMap<String, (f64, Option<f64>)>,
+}
+
+impl FuelFactors {
+    pub(crate) fn load(paths: &FilePaths) -> Self {
+        let factors_file = paths.fuel_factors_file();
+        let mut map = BTreeMap::new();
+        for result in load_csv_file(&factors_file, ',').deserialize() {
+            let row: FuelFactorsRow = result.unwrap();
+            let combustion = (row.ch4 * GWP_CH4 + row.n2o * GWP_N2O) / 1000.0;
+            map.insert(row.fuel, (combustion, row.upstream));
+        }
+
+        // otherwise country-level life-cycle emission factors would mix in plants without them
+        let upstream_fuels = map.values().filter(|(_, upstream)| upstream.is_some()).count();
+        assert!(
+            upstream_fuels == 0 || upstream_fuels == map.len(),
+            "upstream emissions must be given for all fuels or none in {factors_file:?}"
+        );
+
+        Self { map }
+    }
+
+    /// CH4 and N2O emissions from combustion and upstream emissions in t CO2eq, for the fuel
+    /// input that causes the given direct CO2 emissions. Upstream emissions are only known if
+    /// fuel_factors.csv provides them.
+    pub(crate) fn emissions(&self, fuel: &str, co2_emissions: f64) -> (f64, Option<f64>) {
+        let (combustion, upstream) = self
+            .map
+            .get(fuel)
+            .unwrap_or_else(|| panic!("no emission factors for fuel {fuel} in fuel_factors.csv"));
+        let fuel_input = co2_emissions / fuel_co2_intensity(fuel).unwrap();
+        (fuel_input * combustion, upstream.map(|upstream| fuel_input * upstream))
+    }
+}
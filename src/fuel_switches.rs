@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use crate::{generation_emission_match::fuel_emission_factor_range, history::load_history};
+
+/// Fuels that a plant might have been converted to, in order of preference
+const FUELS: [&str; 4] = ["gas", "oil", "coal", "lignite"];
+
+/// Finds plants whose ETS emissions per MWh electricity dropped by at least `min_drop` percent
+/// compared to the previous year with emission data, and prints them. If the emission factor fit
+/// the plant's Entso-E fuel before but doesn't anymore, the plant was likely converted to another
+/// fuel while its Entso-E production type still shows the old one, so a setting for
+/// manual_matches.csv is suggested. Returns the number of likely conversions.
+pub(crate) fn detect_fuel_switches(data_dir: &Path, min_drop: f64) -> usize {
+    let history = load_history(data_dir);
+
+    let mut conversions = 0;
+    for plants in history.values() {
+        let plants: Vec<_> =
+            plants.iter().filter(|p| p.emission_factor > 0.0 && p.generation_el > 0.0).collect();
+
+        for pair in plants.windows(2) {
+            let [previous, plant] = pair else { unreachable!() };
+            let drop = 100.0 * (1.0 - plant.emission_intensity() / previous.emission_intensity());
+            if drop < min_drop {
+                continue;
+            }
+
+            let ef = plant.emission_factor;
+            let fits_fuel = |fuel: &str, ef: f64| {
+                fuel_emission_factor_range(fuel).is_some_and(|range| range.contains(&ef))
+            };
+            let fits = |fuel: &str| fits_fuel(fuel, ef);
+            // a plant that didn't fit its fuel before has other problems than a conversion
+            let suggestion =
+                if fits(&plant.fuel) || !fits_fuel(&plant.fuel, previous.emission_factor) {
+                    None
+                } else if let Some(fuel) = FUELS.into_iter().find(|fuel| fits(fuel)) {
+                    Some(format!("fuel:{fuel}"))
+                } else if FUELS
+                    .iter()
+                    .all(|f| fuel_emission_factor_range(f).is_some_and(|r| ef < r.start))
+                {
+                    // below any fossil fuel, e.g. converted to biomass
+                    let min = (0.8 * ef / 10.0).floor() * 10.0;
+                    Some(format!("plausible-emission-factor-range:{min}-3000"))
+                } else {
+                    None
+                };
+
+            println!(
+                "{} {} ({}, {}): emissions per MWh dropped by {drop:.0}% since {}, emission \
+                 factor {ef:.0} for {}",
+                plant.year,
+                plant.plant_id,
+                plant.country,
+                plant.generation,
+                previous.year,
+                if plant.fuel.is_empty() { "mixed fuels" } else { &plant.fuel },
+            );
+            if let Some(suggestion) = suggestion {
+                println!(
+                    "  likely conversion, add setting \"{suggestion}\" from {} on",
+                    plant.year
+                );
+                conversions += 1;
+            }
+        }
+    }
+
+    conversions
+}
@@ -2,7 +2,9 @@ use std::{collections::BTreeMap, ops::Range};
 
 use serde::Serialize;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+use crate::allocation::AllocationMethod;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub(crate) struct YearlyEmission {
     pub(crate) country: String,
     pub(crate) name: String,
@@ -12,7 +14,7 @@ pub(crate) struct YearlyEmission {
     pub(crate) sigma: f64,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub(crate) struct YearlyGeneration {
     pub(crate) country: String,
     pub(crate) name: String,
@@ -21,10 +23,28 @@ pub(crate) struct YearlyGeneration {
     pub(crate) output: f64,
 }
 
-#[derive(Debug, Default, Serialize)]
+/// How the generation units of a match were linked to their ETS records
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Provenance {
+    #[default]
+    Manual,
+    /// generation unit and ETS record names share the same key
+    AutoExactKey,
+    /// the only ETS record with a very similar key, in case no ETS record has the same key (only
+    /// with `--fuzzy`)
+    Fuzzy,
+    /// EIC <-> ETS installation links of an external data set, see `load_external_matches`
+    External,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
 pub(crate) struct Match {
+    /// stable across years, see `assign_plant_ids`
+    pub(crate) plant_id: String,
     pub(crate) country: String,
     pub(crate) name: String,
+    pub(crate) provenance: Provenance,
     #[serde(serialize_with = "join_vec")]
     pub(crate) generation: Vec<YearlyGeneration>,
     #[serde(serialize_with = "join_vec")]
@@ -32,17 +52,65 @@ pub(crate) struct Match {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     ignore_reason: Option<String>,
+    /// ETS records that might belong to an ignored match, as references for manual_matches.csv
+    #[serde(serialize_with = "join_opt_vec", skip_serializing_if = "Option::is_none")]
+    candidates: Option<Vec<String>>,
+    /// name and emissions of each candidate, to recognize the records
+    #[serde(serialize_with = "join_opt_vec", skip_serializing_if = "Option::is_none")]
+    candidate_details: Option<Vec<String>>,
 
+    /// the main fuel for mixed-fuel plants, see `split_fuels`
     pub(crate) fuel: Option<String>,
+    #[serde(serialize_with = "join_fuel_shares")]
+    pub(crate) fuel_shares: Vec<FuelShare>,
     pub(crate) sigma: f64,
+    pub(crate) emission_share: f64,
     pub(crate) generation_el: f64,
     pub(crate) generation_heat: f64,
     pub(crate) emissions_heat: f64,
     pub(crate) emissions_el: f64,
+    /// CO2 from burning biomass, which is not included in the ETS emissions
+    pub(crate) emissions_el_biogenic: f64,
+    /// applied efficiencies, unless set by the manual match they come from efficiencies.csv
+    pub(crate) efficiency_heat: Option<f64>,
+    pub(crate) efficiency_el: Option<f64>,
+    /// biomass share of the fuel input, unless set by the manual match it comes from
+    /// biomass_shares.csv
+    pub(crate) cofiring_share: Option<f64>,
+    pub(crate) allocation_method: AllocationMethod,
+    /// fossil emissions per MWh of all electricity, including the part generated from biomass
     pub(crate) emission_factor: f64,
+    /// fossil emissions per MWh of electricity generated from fossil fuels
+    pub(crate) emission_factor_fossil: f64,
+    /// fossil and biogenic emissions per MWh of all electricity
+    pub(crate) emission_factor_incl_biogenic: f64,
+    /// including CH4 and N2O from combustion, and also upstream emissions, only calculated in
+    /// CO2eq mode (the latter only if fuel_factors.csv provides upstream emissions)
+    pub(crate) emission_factor_co2eq: Option<f64>,
+    pub(crate) emission_factor_lifecycle: Option<f64>,
+    /// uncertainty bands, only calculated in uncertainty mode
+    pub(crate) emission_factor_p5: Option<f64>,
+    pub(crate) emission_factor_p50: Option<f64>,
+    pub(crate) emission_factor_p95: Option<f64>,
+    /// 0 to 1, see `assess_confidence`
+    pub(crate) confidence: f64,
+    #[serde(serialize_with = "join_vec")]
+    pub(crate) overrides: Vec<String>,
 
+    /// set by the manual match, otherwise `default_plausible_emission_factor_range` applies
+    #[serde(skip)]
+    pub(crate) plausible_emission_factor: Option<Range<f64>>,
+    #[serde(skip)]
+    pub(crate) heat_output: Option<f64>,
+    /// CH4 and N2O emissions from combustion and upstream emissions in t CO2eq attributed to
+    /// electricity, only calculated in CO2eq mode
+    #[serde(skip)]
+    pub(crate) emissions_el_non_co2: f64,
     #[serde(skip)]
-    pub(crate) plausible_emission_factor: Range<f64>,
+    pub(crate) emissions_el_upstream: Option<f64>,
+    /// electricity emissions of each uncertainty mode run
+    #[serde(skip)]
+    pub(crate) emissions_el_samples: Vec<f64>,
 }
 
 impl Match {
@@ -51,18 +119,8 @@ impl Match {
         generation: Vec<YearlyGeneration>,
         emission: Vec<YearlyEmission>,
     ) -> Self {
-        let mut output_sum = 0.0;
-        let mut fuel_mix = BTreeMap::<_, f64>::new();
-        for generation in &generation {
-            *fuel_mix.entry(generation.fuel.as_str()).or_default() += generation.output;
-            output_sum += generation.output;
-        }
-
-        let fuel = fuel_mix
-            .into_iter()
-            .filter(|(_, out)| *out >= 0.95 * output_sum)
-            .map(|(fuel, _)| fuel.to_string())
-            .next();
+        let output_sum = generation.iter().map(|g| g.output).sum();
+        let fuel = dominant_fuel(&generation, FUEL_SHARE_THRESHOLD);
 
         let privileged_allocs = emission.iter().map(|e| e.sigma * e.allocations).sum::<f64>();
         let sigma = if privileged_allocs == 0.0 {
@@ -78,29 +136,187 @@ impl Match {
             emission,
             fuel,
             sigma,
+            emission_share: 1.0,
             generation_el: output_sum,
-            plausible_emission_factor: 300.0..3000.0,
             ..Default::default()
         }
     }
 
     pub(crate) fn ignore(&mut self, reason: String) {
+        self.ignore_with_candidates(reason, Vec::new());
+    }
+
+    pub(crate) fn ignore_with_candidates(
+        &mut self,
+        reason: String,
+        candidates: Vec<YearlyEmission>,
+    ) {
         assert!(self.ignore_reason.is_none());
         self.ignore_reason = Some(reason);
+        self.candidates = Some(candidates.iter().map(|e| format!("id:{}", e.id)).collect());
+        self.candidate_details =
+            Some(candidates.iter().map(|e| format!("{} ({:.0} t)", e.name, e.emissions)).collect());
+    }
+
+    /// Whether the fuel was set in manual_matches.csv instead of derived from the generation units
+    pub(crate) fn has_manual_fuel(&self) -> bool {
+        self.overrides.iter().any(|setting| setting.starts_with("fuel:"))
     }
 
     pub(crate) fn is_ignored(&self) -> bool {
         self.ignore_reason.is_some()
     }
+
+    pub(crate) fn ignore_reason(&self) -> Option<&str> {
+        self.ignore_reason.as_deref()
+    }
+
+    /// Splits a mixed-fuel match between the fuels of its generation units. Each fuel gets the
+    /// share of the generation of its units, and a share of the emissions in proportion to that
+    /// generation weighted by the fuel's CO2 intensity. The fuel with the largest share of the
+    /// emissions becomes the main fuel, whose assumptions (e.g. efficiencies) are used for the
+    /// whole plant. Fails if a fuel's CO2 intensity is unknown.
+    pub(crate) fn split_fuels(&mut self) -> Result<(), String> {
+        let mut generation = BTreeMap::<&str, f64>::new();
+        for g in &self.generation {
+            *generation.entry(g.fuel.as_str()).or_default() += g.output;
+        }
+
+        let mut weights = BTreeMap::new();
+        for (fuel, output) in &generation {
+            let intensity = fuel_co2_intensity(fuel)
+                .ok_or_else(|| format!("uses mixed fuels, including {fuel}"))?;
+            weights.insert(*fuel, output * intensity);
+        }
+
+        let output_sum: f64 = generation.values().sum();
+        let weight_sum: f64 = weights.values().sum();
+        self.fuel_shares = generation
+            .iter()
+            .map(|(fuel, output)| FuelShare {
+                fuel: fuel.to_string(),
+                generation: output / output_sum,
+                emissions: weights[fuel] / weight_sum,
+                ..Default::default()
+            })
+            .collect();
+        let main_share = self.fuel_shares.iter().max_by(|a, b| a.emissions.total_cmp(&b.emissions));
+        self.fuel = main_share.map(|share| share.fuel.clone());
+        Ok(())
+    }
+
+    /// Only attributes the given share of the ETS records' emissions and allocations to this match,
+    /// for ETS installations that include other (industrial) processes.
+    pub(crate) fn set_emission_share(&mut self, share: f64) {
+        assert!(self.emission_share == 1.0, "emission share set twice");
+        for emission in &mut self.emission {
+            emission.emissions *= share;
+            emission.allocations *= share;
+        }
+        self.emission_share = share;
+    }
+}
+
+/// Part of a match that is attributed to one fuel in the country-level stats
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FuelShare {
+    pub(crate) fuel: String,
+    /// share of the electricity generation
+    pub(crate) generation: f64,
+    /// share of the emissions
+    pub(crate) emissions: f64,
+    /// CH4 and N2O emissions from combustion and upstream emissions in t CO2eq of this fuel's
+    /// share of the electricity emissions, only calculated in CO2eq mode
+    pub(crate) emissions_el_non_co2: f64,
+    pub(crate) emissions_el_upstream: Option<f64>,
+}
+
+impl FuelShare {
+    /// The whole match, for plants with one fuel
+    pub(crate) fn single(fuel: &str) -> Self {
+        Self { fuel: fuel.to_string(), generation: 1.0, emissions: 1.0, ..Default::default() }
+    }
+}
+
+/// Share of the output that one fuel needs to provide for a match to be attributed to that fuel
+pub(crate) const FUEL_SHARE_THRESHOLD: f64 = 0.95;
+
+/// The fuel providing at least the given share of the generation units' output, if any
+pub(crate) fn dominant_fuel(generation: &[YearlyGeneration], threshold: f64) -> Option<String> {
+    let mut output_sum = 0.0;
+    let mut fuel_mix = BTreeMap::<_, f64>::new();
+    for generation in generation {
+        *fuel_mix.entry(generation.fuel.as_str()).or_default() += generation.output;
+        output_sum += generation.output;
+    }
+
+    fuel_mix
+        .into_iter()
+        .filter(|(_, out)| *out >= threshold * output_sum)
+        .map(|(fuel, _)| fuel.to_string())
+        .next()
+}
+
+/// Default CO2 emission factors for stationary combustion in the energy industries, in t CO2 per TJ
+/// of fuel input (2006 IPCC Guidelines, Vol. 2, Table 2.2).
+pub(crate) fn fuel_co2_intensity(fuel: &str) -> Option<f64> {
+    match fuel {
+        "gas" => Some(56.1),
+        "coal" => Some(94.6),
+        "lignite" => Some(101.0),
+        "oil" => Some(77.4),
+        _ => None,
+    }
+}
+
+/// Electrical efficiencies that are plausible for thermal power plants
+pub(crate) const PLAUSIBLE_EFFICIENCY_EL: Range<f64> = 0.25..0.62;
+
+/// Emission factors in kg CO2 per MWh electricity that are plausible for a plant running on the
+/// given fuel, based on its CO2 intensity and `PLAUSIBLE_EFFICIENCY_EL`
+pub(crate) fn fuel_emission_factor_range(fuel: &str) -> Option<Range<f64>> {
+    // t CO2 per TJ fuel -> kg CO2 per MWh fuel
+    let intensity = fuel_co2_intensity(fuel)? * 3.6;
+    Some(intensity / PLAUSIBLE_EFFICIENCY_EL.end..intensity / PLAUSIBLE_EFFICIENCY_EL.start)
+}
+
+/// Emission factors that pass the plausibility check for the given fuels, unless set by the
+/// manual match: `fuel_emission_factor_range`, widened to allow for errors of the heat estimate.
+/// The ranges of mixed-fuel plants are weighted by the fuels' share of the generation.
+pub(crate) fn default_plausible_emission_factor_range(fuel_shares: &[FuelShare]) -> Range<f64> {
+    let (mut start, mut end) = (0.0, 0.0);
+    for share in fuel_shares {
+        let range = fuel_emission_factor_range(&share.fuel)
+            .map_or(300.0..3000.0, |range| 0.8 * range.start..1.5 * range.end);
+        start += share.generation * range.start;
+        end += share.generation * range.end;
+    }
+    start..end
 }
 
-fn join_vec<S>(vec: &[impl AsRef<str>], serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn join_vec<S>(vec: &[impl AsRef<str>], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
     serializer.serialize_str(&vec.iter().map(AsRef::as_ref).collect::<Vec<_>>().join("|"))
 }
 
+fn join_fuel_shares<S>(shares: &[FuelShare], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let shares: Vec<_> =
+        shares.iter().map(|share| format!("{}:{:.3}", share.fuel, share.emissions)).collect();
+    join_vec(&shares, serializer)
+}
+
+fn join_opt_vec<S>(vec: &Option<Vec<String>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    join_vec(vec.as_deref().unwrap_or_default(), serializer)
+}
+
 impl AsRef<str> for YearlyGeneration {
     fn as_ref(&self) -> &str {
         &self.name
@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, path::Path};
+
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+
+use crate::{FilePaths, load_csv_file};
+
+/// A plant's row in the powerplants.csv or ignored_powerplants.csv output of one year
+#[derive(Deserialize)]
+pub(crate) struct PlantYear {
+    #[serde(skip)]
+    pub(crate) year: u32,
+    pub(crate) plant_id: String,
+    pub(crate) country: String,
+    pub(crate) generation: String,
+    #[serde(default)]
+    pub(crate) ignore_reason: Option<String>,
+    pub(crate) fuel: String,
+    pub(crate) sigma: f64,
+    pub(crate) generation_el: f64,
+    pub(crate) generation_heat: f64,
+    pub(crate) emissions_heat: f64,
+    pub(crate) emissions_el: f64,
+    pub(crate) emission_factor: f64,
+}
+
+impl PlantYear {
+    pub(crate) fn heat_share(&self) -> f64 {
+        self.generation_heat / (self.generation_heat + self.generation_el)
+    }
+
+    /// ETS emissions per MWh electricity in kg CO2, before any are attributed to heat
+    pub(crate) fn emission_intensity(&self) -> f64 {
+        (self.emissions_heat + self.emissions_el) * 1000.0 / self.generation_el
+    }
+}
+
+/// Loads the powerplants.csv and ignored_powerplants.csv outputs of all processed years, grouped
+/// by plant ID and ordered by year. Years that were processed before plant IDs were introduced are
+/// skipped.
+pub(crate) fn load_history(data_dir: &Path) -> BTreeMap<String, Vec<PlantYear>> {
+    let mut years: Vec<u32> = std::fs::read_dir(data_dir)
+        .unwrap()
+        .filter_map(|entry| entry.unwrap().file_name().to_str()?.parse().ok())
+        .collect();
+    years.sort_unstable();
+
+    let mut history = BTreeMap::<_, Vec<PlantYear>>::new();
+    for year in years {
+        let paths = FilePaths::new(data_dir.to_path_buf(), year);
+        for plants_file in [paths.out_powerplants_file(), paths.ignored_powerplants_file()] {
+            if !plants_file.exists() {
+                continue;
+            }
+
+            let mut csv_reader = load_csv_file(&plants_file, ',');
+            if !csv_reader.headers().unwrap().iter().any(|header| header == "plant_id") {
+                println!("Info: {plants_file:?} has no plant IDs, process {year} again");
+                continue;
+            }
+
+            for result in csv_reader.deserialize() {
+                let plant: PlantYear = result.unwrap();
+                let plants = history.entry(plant.plant_id.clone()).or_default();
+                plants.push(PlantYear { year, ..plant });
+            }
+        }
+    }
+
+    history
+}
+
+#[derive(Serialize)]
+struct HistoryEntry<'a> {
+    plant_id: &'a str,
+    year: u32,
+    country: &'a str,
+    fuel: &'a str,
+    generation: &'a str,
+    emission_factor: f64,
+    generation_el: f64,
+    heat_share: f64,
+    sigma: f64,
+    emission_factor_change_percentage: Option<f64>,
+    generation_el_change_percentage: Option<f64>,
+    heat_share_change_points: Option<f64>,
+    sigma_change_points: Option<f64>,
+    flags: String,
+}
+
+/// Writes every plant's emission factor, generation, heat share and sigma for all processed years,
+/// along with the change compared to the previous year the plant was included in. Changes above
+/// `threshold` (in percent, or percentage points for heat share and sigma) are flagged, since they
+/// usually mean a fuel switch, a retrofit or a matching mistake.
+pub(crate) fn compare_years(data_dir: &Path, threshold: f64) {
+    let history = load_history(data_dir);
+
+    let output_file = data_dir.join("plant_history.csv");
+    let mut csv_writer = Writer::from_path(&output_file).unwrap();
+    let (mut plant_years, mut flagged_plant_years) = (0, 0);
+    for plants in history.values() {
+        let plants: Vec<_> = plants.iter().filter(|p| p.ignore_reason.is_none()).collect();
+        for (i, plant) in plants.iter().enumerate() {
+            let previous = i.checked_sub(1).map(|i| plants[i]);
+            let relative =
+                |f: fn(&PlantYear) -> f64| previous.map(|p| 100.0 * (f(plant) - f(p)) / f(p));
+            let points = |f: fn(&PlantYear) -> f64| previous.map(|p| 100.0 * (f(plant) - f(p)));
+
+            let mut entry = HistoryEntry {
+                plant_id: &plant.plant_id,
+                year: plant.year,
+                country: &plant.country,
+                fuel: &plant.fuel,
+                generation: &plant.generation,
+                emission_factor: plant.emission_factor,
+                generation_el: plant.generation_el,
+                heat_share: plant.heat_share(),
+                sigma: plant.sigma,
+                emission_factor_change_percentage: relative(|p| p.emission_factor),
+                generation_el_change_percentage: relative(|p| p.generation_el),
+                heat_share_change_points: points(PlantYear::heat_share),
+                sigma_change_points: points(|p| p.sigma),
+                flags: String::new(),
+            };
+
+            let flags: Vec<_> = [
+                ("emission_factor", entry.emission_factor_change_percentage),
+                ("generation_el", entry.generation_el_change_percentage),
+                ("heat_share", entry.heat_share_change_points),
+                ("sigma", entry.sigma_change_points),
+            ]
+            .into_iter()
+            .filter(|(_, change)| change.is_some_and(|change| change.abs() > threshold))
+            .map(|(metric, _)| metric)
+            .collect();
+            entry.flags = flags.join("|");
+
+            plant_years += 1;
+            if !flags.is_empty() {
+                flagged_plant_years += 1;
+            }
+            csv_writer.serialize(entry).unwrap();
+        }
+    }
+    csv_writer.flush().unwrap();
+
+    println!(
+        "Flagged {flagged_plant_years} of {plant_years} plant years with changes above \
+         {threshold}%, see {output_file:?}"
+    );
+}
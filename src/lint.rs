@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, BTreeSet, btree_map::Entry};
+
+use crate::{
+    FilePaths, closest_names,
+    country_codes::{CountryCodes, Dataset},
+    generation_emission_match::{YearlyEmission, YearlyGeneration},
+    load_csv_file,
+    manual_matches::{
+        GenerationPattern, ManualMatch, check_settings, manual_matches_for_year, parse_settings,
+    },
+    name_keys::StopWords,
+};
+
+/// Checks the manual matches for a year for problems, reporting all of them instead of stopping at
+/// the first one like `load_manual_matches` does. Returns the number of problems found.
+pub(crate) fn lint_manual_matches(paths: &FilePaths) -> usize {
+    let generation: Vec<YearlyGeneration> =
+        load_csv_file(&paths.generation_file(), ',').deserialize().map(Result::unwrap).collect();
+    let mut emission: Vec<YearlyEmission> =
+        load_csv_file(&paths.emissions_file(), ',').deserialize().map(Result::unwrap).collect();
+    let country_codes = CountryCodes::load(paths);
+    for em in &mut emission {
+        em.country = country_codes.normalize(Dataset::Ets, &em.country);
+    }
+    let manual_matches = manual_matches_for_year(paths);
+    let stop_words = StopWords::load(paths);
+
+    let mut problems = Vec::new();
+
+    // resolved generation / emission indices for each line, `None` if something didn't resolve
+    let mut resolved = Vec::new();
+    // generation / emission index -> first line (index into `manual_matches`) using it
+    let mut used_generation = BTreeMap::<usize, usize>::new();
+    let mut used_emission = BTreeMap::<usize, usize>::new();
+
+    for (line, m) in manual_matches.iter().enumerate() {
+        if m.is_comment() {
+            if m.comment.is_empty() {
+                problems.push((line, "empty line".to_string()));
+            }
+            resolved.push(None);
+            continue;
+        }
+
+        if m.generation_names().next().is_none() {
+            problems.push((line, "no generation units listed".to_string()));
+        }
+
+        let mut all_resolved = true;
+
+        let mut gen_indices = Vec::new();
+        for name in m.generation_names() {
+            if let Some(pattern) = GenerationPattern::parse(name) {
+                let pattern = match pattern {
+                    Ok(pattern) => pattern,
+                    Err(e) => {
+                        problems.push((line, e));
+                        all_resolved = false;
+                        continue;
+                    }
+                };
+
+                let found: Vec<_> =
+                    (0..generation.len()).filter(|&i| pattern.matches(&generation[i])).collect();
+                if found.is_empty() {
+                    problems.push((line, format!("\"{name}\" matches no generation units")));
+                    all_resolved = false;
+                }
+                for i in found {
+                    if let Some(first_line) = first_use(&mut used_generation, i, line) {
+                        let first = &manual_matches[first_line].location;
+                        let unit = &generation[i].name;
+                        let problem = format!("\"{name}\" captures \"{unit}\", used in {first}");
+                        problems.push((line, problem));
+                    }
+                    gen_indices.push(i);
+                }
+                continue;
+            }
+
+            let found: Vec<_> = generation
+                .iter()
+                .enumerate()
+                .filter(|(_, g)| g.name == name || name.strip_prefix("eic:") == Some(&g.eic))
+                .map(|(i, _)| i)
+                .collect();
+
+            match found.as_slice() {
+                [] => {
+                    let candidates = generation.iter().map(|g| g.name.as_str());
+                    let problem = format!("generation \"{name}\" not found");
+                    problems.push((line, with_suggestions(problem, name, candidates)));
+                    all_resolved = false;
+                }
+                [i] => {
+                    if let Some(first_line) = first_use(&mut used_generation, *i, line) {
+                        let first = &manual_matches[first_line].location;
+                        let problem = format!("generation \"{name}\" already used in {first}");
+                        problems.push((line, problem));
+                    }
+                    gen_indices.push(*i);
+                }
+                _ => {
+                    let problem = format!("generation \"{name}\" matches {} units", found.len());
+                    problems.push((line, problem));
+                    all_resolved = false;
+                }
+            }
+        }
+
+        let mut em_indices = Vec::new();
+        for name in m.emission_names() {
+            let found: Vec<_> = emission
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.name == name || name.strip_prefix("id:") == Some(&e.id))
+                .map(|(i, _)| i)
+                .collect();
+
+            match found.as_slice() {
+                [] => {
+                    let candidates = emission.iter().map(|e| e.name.as_str());
+                    let problem = format!("emission \"{name}\" not found");
+                    problems.push((line, with_suggestions(problem, name, candidates)));
+                    all_resolved = false;
+                }
+                [i] => {
+                    if let Some(first_line) = first_use(&mut used_emission, *i, line) {
+                        let first = &manual_matches[first_line].location;
+                        let problem = format!("emission \"{name}\" already used in {first}");
+                        problems.push((line, problem));
+                    }
+                    em_indices.push(*i);
+                }
+                _ => {
+                    let problem = format!(
+                        "emission \"{name}\" matches {} ETS records, use \"id:\" instead",
+                        found.len()
+                    );
+                    problems.push((line, problem));
+                    all_resolved = false;
+                }
+            }
+        }
+
+        let mut settings = Vec::new();
+        for setting in parse_settings(&m.settings) {
+            match setting {
+                Ok(setting) => settings.push(setting),
+                Err(e) => problems.push((line, e)),
+            }
+        }
+        if let Err(e) = check_settings(&settings) {
+            problems.push((line, e));
+        }
+
+        resolved.push(all_resolved.then_some((gen_indices, em_indices)));
+    }
+
+    for ((line, m), resolved) in manual_matches.iter().enumerate().zip(&resolved) {
+        let Some((gen_indices, em_indices)) = resolved else {
+            continue;
+        };
+
+        let used_elsewhere = |gen_i: Option<usize>, em_i: Option<usize>| {
+            gen_i.is_some_and(|i| used_generation.get(&i).is_some_and(|&l| l != line))
+                || em_i.is_some_and(|i| used_emission.get(&i).is_some_and(|&l| l != line))
+        };
+        let (generation, emission) = (&generation, &emission);
+        if is_redundant(
+            m,
+            gen_indices,
+            em_indices,
+            generation,
+            emission,
+            &stop_words,
+            used_elsewhere,
+        ) {
+            problems.push((line, "redundant, automatic matching finds this match".to_string()));
+        }
+    }
+
+    problems.sort();
+    for (line, problem) in &problems {
+        println!("{}: {problem}", manual_matches[*line].location);
+    }
+
+    problems.len()
+}
+
+/// Records the line as the first one using the given generation unit / ETS record, unless another
+/// line used it before. Returns that other line.
+fn first_use(used: &mut BTreeMap<usize, usize>, i: usize, line: usize) -> Option<usize> {
+    match used.entry(i) {
+        Entry::Occupied(first) => Some(*first.get()),
+        Entry::Vacant(entry) => {
+            entry.insert(line);
+            None
+        }
+    }
+}
+
+/// Checks whether automatic matching would generate the same match if the line was removed.
+fn is_redundant(
+    m: &ManualMatch,
+    gen_indices: &[usize],
+    em_indices: &[usize],
+    generation: &[YearlyGeneration],
+    emission: &[YearlyEmission],
+    stop_words: &StopWords,
+    used_elsewhere: impl Fn(Option<usize>, Option<usize>) -> bool,
+) -> bool {
+    // filtered units and settings would get lost without the manual match
+    let [em_i] = em_indices else {
+        return false;
+    };
+    if !m.settings.is_empty() {
+        return false;
+    }
+
+    let country = &emission[*em_i].country;
+    let key = stop_words.key(country, &emission[*em_i].name);
+    if key.is_empty() {
+        return false;
+    }
+
+    let auto_generation: BTreeSet<_> = (0..generation.len())
+        .filter(|&i| !used_elsewhere(Some(i), None))
+        .filter(|&i| {
+            generation[i].country == *country && stop_words.key(country, &generation[i].name) == key
+        })
+        .collect();
+    let auto_emission: BTreeSet<_> = (0..emission.len())
+        .filter(|&i| !used_elsewhere(None, Some(i)))
+        .filter(|&i| {
+            emission[i].country == *country && stop_words.key(country, &emission[i].name) == key
+        })
+        .collect();
+
+    auto_generation == gen_indices.iter().copied().collect()
+        && auto_emission == BTreeSet::from([*em_i])
+}
+
+fn with_suggestions<'a>(
+    problem: String,
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> String {
+    let suggestions = closest_names(name, candidates, 3);
+    if suggestions.is_empty() {
+        problem
+    } else {
+        format!("{problem}, did you mean \"{}\"?", suggestions.join("\", \""))
+    }
+}
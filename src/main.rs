@@ -5,15 +5,43 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use allocation::{AllocationMethod, Efficiencies};
+use cofiring::{BiomassShares, biogenic_emissions};
+use country_codes::{CountryCodes, Dataset, report_unmapped};
 use csv::{Reader, ReaderBuilder, Trim, Writer};
 use deunicode::deunicode;
+use ets_parameters::EtsParameters;
 use file_paths::FilePaths;
-use generation_emission_match::{Match, YearlyEmission, YearlyGeneration};
+use fuel_factors::FuelFactors;
+use generation_emission_match::{
+    FuelShare, Match, PLAUSIBLE_EFFICIENCY_EL, Provenance, YearlyEmission, YearlyGeneration,
+    default_plausible_emission_factor_range, fuel_co2_intensity,
+};
+use manual_matches::load_manual_matches;
+use name_keys::StopWords;
+use sensitivity::Perturbation;
 use serde::{Deserialize, Serialize};
+use uncertainty::{Rng, Uncertainty, percentiles};
 
+mod allocation;
+mod cofiring;
+mod country_codes;
+mod ets_parameters;
 mod file_paths;
+mod fuel_factors;
+mod fuel_switches;
 mod generation_emission_match;
+mod history;
+mod lint;
+mod manual_matches;
+mod name_keys;
+mod opsd;
+mod outliers;
+mod plant_registry;
 mod preprocess;
+mod reports;
+mod sensitivity;
+mod uncertainty;
 
 fn main() {
     let mut args = std::env::args().skip(1);
@@ -24,145 +52,128 @@ fn main() {
                 .expect("Must specify `preprocess <year>`")
                 .parse::<u32>()
                 .expect("Not a valid year");
+            ets_parameters::check_year(year);
 
             let paths = FilePaths::new(PathBuf::from("data"), year);
             let ets_countries = preprocess::yearly_emissions(year, &paths);
             preprocess::yearly_generation(&ets_countries, &paths);
         }
-        Some(year_str) => {
-            let year = year_str
+        Some("lint") => {
+            let year = args
+                .next()
+                .expect("Must specify `lint <year>`")
                 .parse::<u32>()
-                .unwrap_or_else(|_| panic!("Not a valid year: \"{year_str}\""));
+                .expect("Not a valid year");
 
             let paths = FilePaths::new(PathBuf::from("data"), year);
-            let mut matches = Vec::new();
-            load_manual_matches(&mut matches, &paths);
-            generate_auto_matches(&mut matches, &paths);
-            filter_matches(&mut matches);
-            calculate_emission_factors(year, &mut matches, &paths);
-            generate_output(&mut matches, &paths);
-        }
-        None => panic!("Must specify a year to process or `preprocess <year>`."),
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct ManualMatch {
-    generation: String,
-    emission: String,
-    settings: String,
-    comment: String,
-}
-
-fn load_manual_matches(out: &mut Vec<Match>, paths: &FilePaths) {
-    let mut manual_matches = Vec::new();
-    let mut load_generation = BTreeMap::<String, Option<YearlyGeneration>>::new();
-    let mut load_emission = BTreeMap::<String, Option<YearlyEmission>>::new();
-
-    let mut csv_reader = load_csv_file(&paths.manual_matches_file(), ',');
-    for result in csv_reader.deserialize() {
-        let m: ManualMatch = result.expect("badly formated manual_matches.csv file!");
-
-        load_generation.extend(m.generation.split('|').map(|name| (name.to_string(), None)));
-        load_emission.extend(m.emission.split('|').map(|name| (name.to_string(), None)));
-
-        manual_matches.push(m);
-    }
-
-    let mut csv_reader = load_csv_file(&paths.generation_file(), ',');
-    for result in csv_reader.deserialize() {
-        let csv_gen: YearlyGeneration = result.unwrap();
-
-        let mut generation = load_generation.get_mut(&csv_gen.name);
-        if generation.is_none() {
-            generation = load_generation.get_mut(&format!("eic:{}", csv_gen.eic));
-        }
-
-        if let Some(generation) = generation {
-            assert!(generation.is_none(), "Found two generation units: \"{}\"", csv_gen.name);
-            *generation = Some(csv_gen);
+            let problems = lint::lint_manual_matches(&paths);
+            if problems > 0 {
+                println!("Found {problems} problems.");
+                std::process::exit(1);
+            }
         }
-    }
-
-    let mut csv_reader = load_csv_file(&paths.emissions_file(), ',');
-    for result in csv_reader.deserialize() {
-        let em: YearlyEmission = result.unwrap();
+        Some("stop-words") => {
+            let year = args
+                .next()
+                .expect("Must specify `stop-words <year>`")
+                .parse::<u32>()
+                .expect("Not a valid year");
 
-        let mut emission = load_emission.get_mut(&em.name);
-        if emission.is_none() {
-            emission = load_emission.get_mut(&format!("id:{}", em.id));
+            let paths = FilePaths::new(PathBuf::from("data"), year);
+            name_keys::suggest_stop_words(&paths);
         }
+        Some("compare") => {
+            let threshold = args.next().map_or(20.0, |threshold| {
+                threshold.parse::<f64>().expect("Not a valid threshold percentage")
+            });
 
-        if let Some(emission) = emission {
-            assert!(emission.is_none(), "Found two ETS emission records: \"{}\"", em.name);
-            *emission = Some(em);
+            history::compare_years(Path::new("data"), threshold);
         }
-    }
+        Some("fuel-switches") => {
+            let min_drop = args.next().map_or(30.0, |min_drop| {
+                min_drop.parse::<f64>().expect("Not a valid drop percentage")
+            });
 
-    for manual_match in manual_matches {
-        if manual_match.emission.is_empty() && manual_match.generation.is_empty() {
-            // so a CSV comment line can be inserted like ",,,DE"
-            continue;
+            let conversions = fuel_switches::detect_fuel_switches(Path::new("data"), min_drop);
+            println!("Found {conversions} likely conversions.");
         }
+        Some("sensitivity") => {
+            let year = args
+                .next()
+                .expect("Must specify `sensitivity <year>`")
+                .parse::<u32>()
+                .expect("Not a valid year");
+            ets_parameters::check_year(year);
 
-        let mut m = Match::new(
-            String::from("Manual Match"),
-            manual_match
-                .generation
-                .split('|')
-                .map(|name| {
-                    load_generation
-                        .remove(name)
-                        .unwrap_or_else(|| panic!("generation \"{name}\" used more than once."))
-                        .unwrap_or_else(|| panic!("generation \"{name}\" not found."))
-                })
-                .collect(),
-            manual_match
-                .emission
-                .split('|')
-                .filter(|name| !name.is_empty())
-                .map(|name| {
-                    load_emission
-                        .remove(name)
-                        .unwrap_or_else(|| panic!("emission \"{name}\" used more than once."))
-                        .unwrap_or_else(|| panic!("emission \"{name}\" not found."))
-                })
-                .collect(),
-        );
-
-        if manual_match.emission.is_empty() {
-            m.ignore(format!("filtered in manual_matches.csv: {}", manual_match.comment));
+            let paths = FilePaths::new(PathBuf::from("data"), year);
+            sensitivity::sensitivity(year, &paths);
         }
+        Some(year_str) => {
+            let year = year_str
+                .parse::<u32>()
+                .unwrap_or_else(|_| panic!("Not a valid year: \"{year_str}\""));
+            ets_parameters::check_year(year);
 
-        for (key, val) in manual_match
-            .settings
-            .split_terminator('|')
-            .map(|s| s.split_once(':').unwrap_or_else(|| panic!("bad setting {s}")))
-        {
-            match key {
-                "plausible-emission-factor-range" => {
-                    let (min_s, max_s) = val.split_once('-').expect("bad emission factor range");
-                    let min = min_s.parse().expect("bad minimum plausible emission factor");
-                    let max = max_s.parse().expect("bad maximum plausible emission factor");
-                    m.plausible_emission_factor = min..max;
+            let paths = FilePaths::new(PathBuf::from("data"), year);
+            let mut options = CalculationOptions::default();
+            let mut fuzzy = false;
+            for arg in args {
+                if let Some(method) = arg.strip_prefix("--allocation=") {
+                    options.allocation_method =
+                        AllocationMethod::parse(method).unwrap_or_else(|e| panic!("{e}"));
+                } else if let Some(runs) = arg.strip_prefix("--uncertainty=") {
+                    let runs = runs
+                        .parse()
+                        .ok()
+                        .filter(|&runs| runs >= 1)
+                        .expect("Not a valid number of runs, must be at least 1");
+                    options.uncertainty = Some(Uncertainty::load(&paths, runs));
+                } else if arg == "--co2eq" {
+                    options.fuel_factors = Some(FuelFactors::load(&paths));
+                } else if arg == "--fuzzy" {
+                    fuzzy = true;
+                } else {
+                    panic!("Unknown argument: \"{arg}\"");
                 }
-                _ => panic!("invalid setting {key}:{val}"),
             }
-        }
 
-        out.push(m);
+            let mut matches = Vec::new();
+            load_manual_matches(&mut matches, &paths);
+            opsd::load_external_matches(&mut matches, &paths);
+            generate_auto_matches(&mut matches, fuzzy, &paths);
+            reports::unmatched_emissions(&matches, &paths);
+            filter_matches(&mut matches);
+            let degdays = load_degree_days(year, &paths);
+            calculate_emission_factors(year, &options, &degdays, &mut matches, &paths);
+            plant_registry::assign_plant_ids(&mut matches, &paths);
+            assess_confidence(&mut matches, &paths);
+            generate_output(&mut matches, options.allocation_method, &paths);
+            reports::ets_coverage(&matches, &paths);
+            reports::worklist(&matches, &paths);
+        }
+        None => panic!(
+            "Must specify a year to process, `preprocess <year>`, `lint <year>`, \
+             `stop-words <year>`, `sensitivity <year>`, `compare [threshold]` or \
+             `fuel-switches [min-drop]`."
+        ),
     }
 }
 
-fn generate_auto_matches(matches: &mut Vec<Match>, paths: &FilePaths) {
+/// Matches generation units and ETS records that share a name key. With `fuzzy`, generation units
+/// without such ETS records get the only unclaimed one with a very similar key.
+fn generate_auto_matches(matches: &mut Vec<Match>, fuzzy: bool, paths: &FilePaths) {
     let manual_map_generation: BTreeSet<_> =
         matches.iter().flat_map(|m| &m.generation).map(|g| &g.name).collect();
     let manual_map_emission: BTreeSet<_> =
         matches.iter().flat_map(|m| &m.emission).map(|g| &g.name).collect();
-    let manual_match_keys: BTreeSet<_> = manual_map_generation
+    let stop_words = StopWords::load(paths);
+    let manual_match_keys: BTreeSet<_> = matches
         .iter()
-        .chain(manual_map_emission.iter())
-        .map(|name| get_key(name))
+        .flat_map(|m| {
+            let names =
+                m.generation.iter().map(|g| &g.name).chain(m.emission.iter().map(|e| &e.name));
+            names.map(|name| stop_words.key(&m.country, name))
+        })
         .collect();
 
     let mut auto_matches = BTreeMap::<_, (Vec<YearlyGeneration>, Vec<YearlyEmission>)>::new();
@@ -175,37 +186,82 @@ fn generate_auto_matches(matches: &mut Vec<Match>, paths: &FilePaths) {
             continue;
         }
 
-        let key = get_key(&csv_gen.name);
+        let key = stop_words.key(&csv_gen.country, &csv_gen.name);
         auto_matches.entry((csv_gen.country.to_string(), key)).or_default().0.push(csv_gen);
     }
 
+    // all ETS records not used by manual matches, by country, to suggest candidates
+    let mut unclaimed_emission = BTreeMap::<String, Vec<(String, YearlyEmission)>>::new();
+
+    let country_codes = CountryCodes::load(paths);
     let mut csv_reader = load_csv_file(&paths.emissions_file(), ',');
     for result in csv_reader.deserialize() {
-        let em: YearlyEmission = result.unwrap();
+        let mut em: YearlyEmission = result.unwrap();
 
         if manual_map_emission.contains(&em.name) {
             continue;
         }
 
-        let key = get_key(&em.name);
+        em.country = country_codes.normalize(Dataset::Ets, &em.country);
+        let key = stop_words.key(&em.country, &em.name);
         if !key.is_empty() {
-            // some power stations use the "XI" country code in emissions data and "IE" in generation data
-            let country = match em.country.as_str() {
-                "XI" | "NIE" => "IE",
-                other => other,
-            };
-            auto_matches.entry((country.to_string(), key)).and_modify(|m| m.1.push(em));
+            let unclaimed = unclaimed_emission.entry(em.country.clone()).or_default();
+            unclaimed.push((key.clone(), em.clone()));
+            auto_matches.entry((em.country.clone(), key)).and_modify(|m| m.1.push(em));
         }
     }
 
-    matches.extend(auto_matches.into_iter().map(|((_, key), (generation, emission))| {
+    // ETS records with a key that no generation unit has, as candidates for fuzzy matches
+    let generation_keys: BTreeSet<_> = auto_matches.keys().cloned().collect();
+    let mut fuzzy_claimed = BTreeSet::new();
+
+    matches.extend(auto_matches.into_iter().map(|((country, key), (generation, mut emission))| {
+        let mut provenance = Provenance::AutoExactKey;
+        if fuzzy && emission.is_empty() && !key.is_empty() {
+            let unclaimed = unclaimed_emission.get(&country).map(Vec::as_slice).unwrap_or_default();
+            let similar: Vec<_> = unclaimed
+                .iter()
+                .filter(|(k, _)| !generation_keys.contains(&(country.clone(), k.clone())))
+                .filter(|(k, _)| strsim::normalized_levenshtein(&key, k) >= 0.85)
+                .map(|(_, em)| em)
+                .collect();
+
+            if let [em] = similar.as_slice()
+                && fuzzy_claimed.insert(em.id.clone())
+            {
+                emission.push((*em).clone());
+                provenance = Provenance::Fuzzy;
+            }
+        }
+
         let mut m = Match::new(key, generation, emission);
+        m.provenance = provenance;
 
         if m.name.is_empty() {
             m.ignore("seems to be a meaningless generation unit name".to_string());
         } else {
-            if m.emission.len() != 1 {
-                m.ignore(format!("found {} possibly matching ETS records", m.emission.len()));
+            if m.emission.len() > 1 {
+                let candidates = m.emission.clone();
+                let reason = format!(
+                    "found {} possibly matching ETS records for key \"{}\"",
+                    m.emission.len(),
+                    m.name
+                );
+                m.ignore_with_candidates(reason, candidates);
+            } else if m.emission.is_empty() {
+                // suggest ETS records with similar keys instead
+                let unclaimed =
+                    unclaimed_emission.get(&country).map(Vec::as_slice).unwrap_or_default();
+                let similar_keys =
+                    closest_names(&m.name, unclaimed.iter().map(|(k, _)| k.as_str()), 3);
+                let candidates = similar_keys
+                    .iter()
+                    .flat_map(|key| unclaimed.iter().filter(move |(k, _)| k == key))
+                    .map(|(_, em)| (*em).clone())
+                    .collect();
+                let reason =
+                    format!("found 0 possibly matching ETS records for key \"{}\"", m.name);
+                m.ignore_with_candidates(reason, candidates);
             }
 
             if manual_match_keys.contains(&m.name) {
@@ -222,31 +278,23 @@ fn generate_auto_matches(matches: &mut Vec<Match>, paths: &FilePaths) {
     }));
 }
 
-fn get_key(name: &str) -> String {
-    #[rustfmt::skip]
-    const IGNORE_WORDS: &[&str] = &[
-        "electrabel", // BE
-        "elektrarn", // CZ
-        "block", "dampf", "energie", "gud", "kraft", "turbine", // DE
-        "generat", "power", "station", // EN
-        "combinado", "electrica", "espana", "endesa", "generacion", "grupo", "iberdrola", // ES
-        "voimalaitos", "lämpökeskus", // FI
-        "electrique", // FR
-        "limited", // GB
-        "gazturbinas", "eromu", // HU
-        "centrale", "energi", "termoelettrica", "turbogas", "combinato", "cogenera", // IT
-        "vattenfall", // NL
-        "cieplownia", "oddzial", "elektrowni", "energetyczny", "wytwarzanie", // PL
-        "central", "termoelectrica", "termoeletrica", "termica", // RO
-    ];
-
-    deunicode(&name.to_lowercase())
-        .split(|c: char| !c.is_alphabetic())
-        .filter(|part| IGNORE_WORDS.iter().all(|i| !part.contains(i)))
-        .filter(|part| part.len() >= 3)
-        .max_by_key(|part| part.len())
-        .unwrap_or_default()
-        .to_string()
+/// Returns up to `count` candidates that are most similar to `name`, most similar first.
+fn closest_names<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    count: usize,
+) -> Vec<&'a str> {
+    let normalize = |s: &str| deunicode(&s.to_lowercase());
+    let name = normalize(name);
+
+    let mut scored: Vec<_> = candidates
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|c| (strsim::normalized_levenshtein(&name, &normalize(c)), c))
+        .filter(|(score, _)| *score >= 0.5)
+        .collect();
+    scored.sort_by(|(x, _), (y, _)| y.total_cmp(x));
+    scored.into_iter().take(count).map(|(_, c)| c).collect()
 }
 
 fn filter_matches(matches: &mut Vec<Match>) {
@@ -258,41 +306,44 @@ fn filter_matches(matches: &mut Vec<Match>) {
             // Ignore already filtered matches
         } else if m.generation_el == 0.0 {
             m.ignore("0 generation".to_string());
-        } else if m.fuel.is_none() {
-            m.ignore("uses mixed fuels".to_string());
         } else if m.emission.iter().map(|e| e.emissions).sum::<f64>() == 0.0 {
             m.ignore("0 emissions".to_string());
+        } else if m.fuel.is_none()
+            && let Err(reason) = m.split_fuels()
+        {
+            m.ignore(reason);
+        }
+
+        if let Some(fuel) = &m.fuel
+            && m.fuel_shares.is_empty()
+        {
+            m.fuel_shares = vec![FuelShare::single(fuel)];
         }
 
         true
     });
 }
 
-fn calculate_emission_factors(year: u32, matches: &mut [Match], paths: &FilePaths) {
-    // Heat emissions estimation. See README.md
-    assert!(year >= 2020, "year < 2020 unsupported");
-
-    let k: f64 = (year - 2020).into();
-    let beta = 0.8782 - (k * 0.022); // This is valid for >= 2020
-    let gamma = 0.3; // This is valid for >= 2020
-
-    // https://climate.ec.europa.eu/system/files/2021-10/policy_ets_allowances_bm_curve_factsheets_en.pdf
-    let heat_benchmark = match year {
-        2013..=2020 => 62.3, // t CO2/TJ
-        2021..=2025 => 47.3, // t CO2/TJ
-        _ => panic!("year {year} is unsupported"),
-    };
-
-    let efficiency_heat = 0.8;
-    let efficiency_el = 0.35;
+/// Options of the emission factor calculation, see README.md
+#[derive(Default)]
+struct CalculationOptions {
+    allocation_method: AllocationMethod,
+    uncertainty: Option<Uncertainty>,
+    fuel_factors: Option<FuelFactors>,
+    /// only used by the sensitivity analysis
+    perturbation: Option<Perturbation>,
+}
 
+/// Heating degree days per country and year, from the Eurostat data. Asks for confirmation if
+/// they don't include the given year yet.
+fn load_degree_days(year: u32, paths: &FilePaths) -> BTreeMap<String, BTreeMap<u32, f64>> {
+    let country_codes = CountryCodes::load(paths);
     let mut degdays = BTreeMap::new();
     let mut csv_reader = load_csv_file(&paths.degree_days_file(), '\t');
 
-    let latest_year: u32 = {
-        let headers = csv_reader.headers().unwrap();
-        headers.get(headers.len() - 1).unwrap().parse().unwrap()
-    };
+    let years: Vec<u32> =
+        csv_reader.headers().unwrap().iter().skip(1).map(|year| year.parse().unwrap()).collect();
+    let latest_year = *years.last().unwrap();
 
     if latest_year < year {
         println!("WARNING! Degree days database does not include data for year {year}.");
@@ -310,62 +361,263 @@ fn calculate_emission_factors(year: u32, matches: &mut [Match], paths: &FilePath
             continue;
         }
 
-        let country = match record_data.next().unwrap() {
-            "EL" => "GR".to_string(), // Greece is Ελλάς / Ellás in this dataset
-            other => other.to_string(),
-        };
+        let country = country_codes.normalize(Dataset::Eurostat, record_data.next().unwrap());
+
+        // values of some years may be missing (":")
+        let values = record.iter().skip(1).map(|value| value.parse::<f64>().ok());
+        let values = years.iter().zip(values).filter_map(|(year, value)| Some((*year, value?)));
+        degdays.insert(country, values.collect::<BTreeMap<_, _>>());
+    }
+
+    degdays
+}
 
-        degdays.insert(
-            country,
-            (2014..=latest_year)
-                .map(|year| record.get((year - 1979) as usize + 1).unwrap().parse().unwrap())
-                .collect::<Vec<f64>>(),
-        );
+fn calculate_emission_factors(
+    year: u32,
+    options: &CalculationOptions,
+    degdays: &BTreeMap<String, BTreeMap<u32, f64>>,
+    matches: &mut [Match],
+    paths: &FilePaths,
+) {
+    // Heat emissions estimation. See README.md
+    let mut params = EtsParameters::load(paths, year);
+    if let Some(perturbation) = &options.perturbation {
+        perturbation.apply_to_parameters(&mut params);
     }
+    let allocation_method = options.allocation_method;
+    let mut rng = Rng::default();
+
+    let efficiencies = Efficiencies::load(paths);
+    let biomass_shares = BiomassShares::load(paths);
+
+    let missing_degdays: BTreeSet<_> = matches
+        .iter()
+        .filter(|m| !m.is_ignored() && !degdays.contains_key(&m.country))
+        .map(|m| m.country.clone())
+        .collect();
+    report_unmapped(Dataset::Eurostat, &missing_degdays);
+    assert!(missing_degdays.is_empty(), "degree days missing for some countries");
 
     for m in matches.iter_mut().filter(|m| !m.is_ignored()) {
-        // average 2014-2018
-        let baseline_degdays = degdays.get(&m.country).unwrap().iter().take(5).sum::<f64>() / 5.0;
-        let current_degdays =
-            degdays.get(&m.country).unwrap().get(year as usize - 2014).unwrap_or(&baseline_degdays);
-
-        let allocation_sum: f64 = m.emission.iter().map(|g| g.allocations).sum();
-        let emission_sum: f64 = m.emission.iter().map(|g| g.emissions).sum();
-
-        // Heat provision can be privileged (some industry types) or non-privileged (e.g. district
-        // heating). For privileged heat provided by a power plant, free allocation of ETS
-        // allowances according to the heat benchmark is granted, reduced only by the linear
-        // reduction factor (beta). Allocations for unprivileged heat are reduced further using
-        // the carbon leakage exposure factor (gamma). Sigma is the share of privileged heat
-        // provided by a power plant.
-        let alloc_privileged = m.sigma * allocation_sum;
-        let alloc_nonpriv = (1.0 - m.sigma) * allocation_sum;
-
-        // "preliminary allocation" = allocation before any reduction factors (beta/gamma) are
-        // applied, so just the result of the ETS heat benchmark.
-        let prelim_privileged = alloc_privileged / beta;
-        let prelim_nonpriv = alloc_nonpriv / (beta * gamma);
-
-        // Non-privileged heat is mostly district heating. Since the allocation is based on the
-        // historical average (2014-2018) of heat provided, scale the preliminary allocation
-        // according to current year's winter temperatures (= heating degree days).
-        let scaled_nonpriv = prelim_nonpriv * (current_degdays / baseline_degdays);
-
-        // 277 MWh in one TJ
-        m.generation_heat = (scaled_nonpriv + prelim_privileged) / (heat_benchmark / 277.777777);
-
-        // "Efficiency method" as described in
-        // https://ghgprotocol.org/sites/default/files/2023-03/CHP_guidance_v1.0.pdf
-        let share_heat = m.generation_heat / efficiency_heat;
-        let share_el = m.generation_el / efficiency_el;
-        m.emissions_heat = emission_sum * share_heat / (share_heat + share_el);
-
-        m.emissions_el = emission_sum - m.emissions_heat;
+        let country_degdays = degdays.get(&m.country).unwrap();
+        let baseline_degdays = params
+            .baseline
+            .clone()
+            .map(|year| {
+                let degdays = country_degdays.get(&year);
+                let country = &m.country;
+                *degdays.unwrap_or_else(|| panic!("{country} degree days missing for year {year}"))
+            })
+            .sum::<f64>()
+            / params.baseline.clone().count() as f64;
+        let current_degdays = country_degdays.get(&year).unwrap_or(&baseline_degdays);
+
+        let (efficiency_heat, efficiency_el) =
+            efficiencies.get(m.fuel.as_deref().unwrap(), &m.country);
+        let mut inputs = HeatInputs {
+            sigma: m.sigma,
+            degdays_ratio: current_degdays / baseline_degdays,
+            efficiency_heat: *m.efficiency_heat.get_or_insert(efficiency_heat),
+            efficiency_el: *m.efficiency_el.get_or_insert(efficiency_el),
+        };
+
+        if let Some(perturbation) = options.perturbation {
+            perturbation.apply_to_inputs(&mut inputs);
+        }
+
+        if let Some(uncertainty) = &options.uncertainty {
+            let samples: Vec<_> = (0..uncertainty.runs)
+                .map(|_| {
+                    let inputs = uncertainty.sample(&inputs, &mut rng);
+                    split_emissions(m, &params, allocation_method, &inputs).2
+                })
+                .collect();
+            let emission_factors =
+                samples.iter().map(|emissions_el| (emissions_el * 1000.0) / m.generation_el);
+            let (p5, p50, p95) = percentiles(emission_factors.collect());
+            m.emission_factor_p5 = Some(p5);
+            m.emission_factor_p50 = Some(p50);
+            m.emission_factor_p95 = Some(p95);
+            m.emissions_el_samples = samples;
+        }
+
+        (m.generation_heat, m.emissions_heat, m.emissions_el) =
+            split_emissions(m, &params, allocation_method, &inputs);
+        m.allocation_method = allocation_method;
         m.emission_factor = (m.emissions_el * 1000.0) / m.generation_el;
 
-        if !m.plausible_emission_factor.contains(&m.emission_factor) {
-            m.ignore("emission factor seems implausible".to_string());
+        // ETS emissions exclude biogenic CO2, while Entso-E generation includes the part of the
+        // electricity generated from biomass
+        let cofiring_share =
+            *m.cofiring_share.get_or_insert_with(|| biomass_shares.get(&m.generation));
+        m.emissions_el_biogenic =
+            biogenic_emissions(m.emissions_el, m.fuel.as_deref().unwrap(), cofiring_share);
+        m.emission_factor_fossil = m.emission_factor / (1.0 - cofiring_share);
+        m.emission_factor_incl_biogenic =
+            ((m.emissions_el + m.emissions_el_biogenic) * 1000.0) / m.generation_el;
+
+        if let Some(fuel_factors) = &options.fuel_factors {
+            (m.emissions_el_non_co2, m.emissions_el_upstream) = (0.0, Some(0.0));
+            for share in &mut m.fuel_shares {
+                let emissions_el = m.emissions_el * share.emissions;
+                let (non_co2, upstream) = fuel_factors.emissions(&share.fuel, emissions_el);
+                (share.emissions_el_non_co2, share.emissions_el_upstream) = (non_co2, upstream);
+                m.emissions_el_non_co2 += non_co2;
+                m.emissions_el_upstream = m.emissions_el_upstream.zip(upstream).map(|(x, y)| x + y);
+            }
+            let emissions_co2eq = m.emissions_el + m.emissions_el_non_co2;
+            m.emission_factor_co2eq = Some((emissions_co2eq * 1000.0) / m.generation_el);
+            m.emission_factor_lifecycle = m
+                .emissions_el_upstream
+                .map(|upstream| ((emissions_co2eq + upstream) * 1000.0) / m.generation_el);
+        }
+
+        let plausible = m
+            .plausible_emission_factor
+            .clone()
+            .unwrap_or_else(|| default_plausible_emission_factor_range(&m.fuel_shares));
+        let ef = m.emission_factor_fossil;
+        if !plausible.contains(&ef) {
+            let deviation = if ef < plausible.start {
+                format!("{:.0}% below", 100.0 * (1.0 - ef / plausible.start))
+            } else {
+                format!("{:.0}% above", 100.0 * (ef / plausible.end - 1.0))
+            };
+            let fuels: Vec<_> = m.fuel_shares.iter().map(|share| share.fuel.as_str()).collect();
+            m.ignore(format!(
+                "emission factor {ef:.0} seems implausible, {deviation} the expected range \
+                 {:.0}-{:.0} for {}",
+                plausible.start,
+                plausible.end,
+                fuels.join("+")
+            ));
+        }
+    }
+
+    outliers::ignore_outliers(matches);
+}
+
+/// Inputs of the heat estimate that are uncertain, see `Uncertainty`
+pub(crate) struct HeatInputs {
+    pub(crate) sigma: f64,
+    /// heating degree days of the current year relative to the baseline period
+    pub(crate) degdays_ratio: f64,
+    pub(crate) efficiency_heat: f64,
+    pub(crate) efficiency_el: f64,
+}
+
+/// Estimates the heat output of a plant from its free allocations and splits its emissions between
+/// heat and electricity. Returns the heat output, heat emissions and electricity emissions.
+fn split_emissions(
+    m: &Match,
+    params: &EtsParameters,
+    allocation_method: AllocationMethod,
+    inputs: &HeatInputs,
+) -> (f64, f64, f64) {
+    let allocation_sum: f64 = m.emission.iter().map(|g| g.allocations).sum();
+    let emission_sum: f64 = m.emission.iter().map(|g| g.emissions).sum();
+
+    // Heat provision can be privileged (some industry types) or non-privileged (e.g. district
+    // heating). For privileged heat provided by a power plant, free allocation of ETS allowances
+    // according to the heat benchmark is granted, reduced only by the linear reduction factor
+    // (beta). Allocations for unprivileged heat are reduced further using the carbon leakage
+    // exposure factor (gamma). Sigma is the share of privileged heat provided by a power plant.
+    let alloc_privileged = inputs.sigma * allocation_sum;
+    let alloc_nonpriv = (1.0 - inputs.sigma) * allocation_sum;
+
+    // "preliminary allocation" = allocation before any reduction factors (beta/gamma) are
+    // applied, so just the result of the ETS heat benchmark.
+    let prelim_privileged = alloc_privileged / params.beta;
+    let prelim_nonpriv = alloc_nonpriv / (params.beta * params.gamma);
+
+    // Non-privileged heat is mostly district heating. Since the allocation is based on the
+    // historical average (baseline period) of heat provided, scale the preliminary allocation
+    // according to current year's winter temperatures (= heating degree days).
+    let scaled_nonpriv = prelim_nonpriv * inputs.degdays_ratio;
+
+    // 277 MWh in one TJ
+    let estimated_heat =
+        (scaled_nonpriv + prelim_privileged) / (params.heat_benchmark / 277.777777);
+    let generation_heat = m.heat_output.unwrap_or(estimated_heat);
+
+    // "Efficiency method" (default) as described in
+    // https://ghgprotocol.org/sites/default/files/2023-03/CHP_guidance_v1.0.pdf
+    let heat_share = allocation_method.heat_share(
+        m,
+        generation_heat,
+        inputs.efficiency_heat,
+        inputs.efficiency_el,
+    );
+    let emissions_heat = emission_sum * heat_share;
+
+    (generation_heat, emissions_heat, emission_sum - emissions_heat)
+}
+
+#[derive(Deserialize)]
+struct PreviousPlant {
+    plant_id: String,
+    emission_factor: f64,
+}
+
+/// Rates each match from 0 to 1, averaging these criteria (if applicable):
+/// - similarity of generation unit and ETS record names (always 1 for manual and external matches)
+/// - plausibility of the electrical efficiency implied by the emission factor
+/// - stability of the emission factor compared to the previous year, by plant ID
+fn assess_confidence(matches: &mut [Match], paths: &FilePaths) {
+    let mut previous = BTreeMap::new();
+    let previous_file = paths.previous_year_powerplants_file();
+    if previous_file.exists() {
+        let mut csv_reader = load_csv_file(&previous_file, ',');
+        if csv_reader.headers().unwrap().iter().any(|header| header == "plant_id") {
+            for result in csv_reader.deserialize() {
+                let plant: PreviousPlant = result.unwrap();
+                previous.insert(plant.plant_id, plant.emission_factor);
+            }
+        } else {
+            println!(
+                "Info: {previous_file:?} has no plant IDs, process the previous year again to \
+                 include the stability of emission factors in the confidence"
+            );
+        }
+    }
+
+    let normalize = |s: &str| deunicode(&s.to_lowercase());
+
+    for m in matches.iter_mut() {
+        let mut scores = Vec::new();
+
+        scores.push(match m.provenance {
+            Provenance::Manual | Provenance::External => 1.0,
+            Provenance::AutoExactKey | Provenance::Fuzzy => m
+                .generation
+                .iter()
+                .flat_map(|g| m.emission.iter().map(move |e| (g, e)))
+                .map(|(g, e)| {
+                    strsim::normalized_levenshtein(&normalize(&g.name), &normalize(&e.name))
+                })
+                .fold(0.0, f64::max),
+        });
+
+        let intensity = m.fuel.as_deref().and_then(fuel_co2_intensity);
+        if let Some(intensity) = intensity
+            && m.emission_factor > 0.0
+        {
+            // t CO2 per TJ fuel -> kg CO2 per MWh fuel
+            let efficiency = (intensity * 3.6) / m.emission_factor;
+            let deviation = (PLAUSIBLE_EFFICIENCY_EL.start - efficiency)
+                .max(efficiency - PLAUSIBLE_EFFICIENCY_EL.end)
+                .max(0.0);
+            scores.push((1.0 - deviation / 0.2).max(0.0));
+        }
+
+        if let Some(previous_ef) = previous.get(&m.plant_id)
+            && m.emission_factor > 0.0
+        {
+            let change = (m.emission_factor - previous_ef).abs() / previous_ef;
+            scores.push((1.0 - change).max(0.0));
         }
+
+        m.confidence = scores.iter().sum::<f64>() / scores.len() as f64;
     }
 }
 
@@ -378,14 +630,47 @@ struct FuelStats {
     coverage_percentage: f64,
     emissions_el: f64,
     emissions_heat: f64,
+    emissions_el_biogenic: f64,
+    allocation_method: AllocationMethod,
     emission_factor: Option<f64>,
+    emission_factor_fossil: Option<f64>,
+    emission_factor_incl_biogenic: Option<f64>,
+    emission_factor_co2eq: Option<f64>,
+    emission_factor_lifecycle: Option<f64>,
+    emission_factor_p5: Option<f64>,
+    emission_factor_p50: Option<f64>,
+    emission_factor_p95: Option<f64>,
+
+    /// matched generation from biomass
+    #[serde(skip)]
+    matched_generation_biogenic: f64,
+    /// only summed up in CO2eq mode
+    #[serde(skip)]
+    emissions_el_non_co2: Option<f64>,
+    #[serde(skip)]
+    emissions_el_upstream: Option<f64>,
+    #[serde(skip)]
+    emissions_el_samples: Vec<f64>,
 }
 
 impl FuelStats {
-    fn add_match(&mut self, m: &Match) {
-        self.matched_generation += m.generation_el;
-        self.emissions_el += m.emissions_el;
-        self.emissions_heat += m.emissions_heat;
+    /// Adds the given fuel's part of a match. CO2 emissions of mixed-fuel plants are split by the
+    /// fuel's share of the emissions, CH4, N2O and upstream emissions are calculated per fuel.
+    fn add_match(&mut self, m: &Match, share: &FuelShare) {
+        let generation = m.generation_el * share.generation;
+        self.matched_generation += generation;
+        self.emissions_el += m.emissions_el * share.emissions;
+        self.emissions_heat += m.emissions_heat * share.emissions;
+        self.emissions_el_biogenic += m.emissions_el_biogenic * share.emissions;
+        self.matched_generation_biogenic += generation * m.cofiring_share.unwrap_or_default();
+        if m.emission_factor_co2eq.is_some() {
+            *self.emissions_el_non_co2.get_or_insert_default() += share.emissions_el_non_co2;
+        }
+        if let Some(upstream) = share.emissions_el_upstream {
+            *self.emissions_el_upstream.get_or_insert_default() += upstream;
+        }
+        let samples: Vec<_> = m.emissions_el_samples.iter().map(|e| e * share.emissions).collect();
+        self.add_samples(&samples);
     }
 
     fn add_stat(&mut self, other: &Self) {
@@ -393,10 +678,26 @@ impl FuelStats {
         self.matched_generation += other.matched_generation;
         self.emissions_el += other.emissions_el;
         self.emissions_heat += other.emissions_heat;
+        self.emissions_el_biogenic += other.emissions_el_biogenic;
+        self.matched_generation_biogenic += other.matched_generation_biogenic;
+        if let Some(non_co2) = other.emissions_el_non_co2 {
+            *self.emissions_el_non_co2.get_or_insert_default() += non_co2;
+        }
+        if let Some(upstream) = other.emissions_el_upstream {
+            *self.emissions_el_upstream.get_or_insert_default() += upstream;
+        }
+        self.add_samples(&other.emissions_el_samples);
+    }
+
+    fn add_samples(&mut self, samples: &[f64]) {
+        self.emissions_el_samples.resize(samples.len().max(self.emissions_el_samples.len()), 0.0);
+        for (sum, sample) in self.emissions_el_samples.iter_mut().zip(samples) {
+            *sum += sample;
+        }
     }
 }
 
-fn generate_output(matches: &mut [Match], paths: &FilePaths) {
+fn generate_output(matches: &mut [Match], allocation_method: AllocationMethod, paths: &FilePaths) {
     matches.sort_unstable_by(|x, y| {
         let cmp_criteria_x = (&x.country, &x.name, &x.generation.first().map(|g| &g.name));
         let cmp_criteria_y = (&y.country, &y.name, &y.generation.first().map(|g| &g.name));
@@ -416,24 +717,58 @@ fn generate_output(matches: &mut [Match], paths: &FilePaths) {
     plants_writer.flush().unwrap();
     ignored_writer.flush().unwrap();
 
-    // Generate country-level stats
-    let mut fuel_stats = BTreeMap::<_, FuelStats>::new();
+    // write country-level stats
+    let mut csv_writer = Writer::from_path(paths.out_countries_file()).unwrap();
+    for mut stat in country_stats(matches, paths).into_values() {
+        stat.allocation_method = allocation_method;
+        csv_writer.serialize(stat).unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
 
-    // sum up all relevant generation per country and fuel (even unmatched), to calculate coverage
+/// Aggregates the valid matches per country and fuel, including all countries (country "") and
+/// coal+lignite.
+/// Sums up the generation of all units per country and fuel, even unmatched ones. Units of plants
+/// with a manually set fuel count for that fuel.
+pub(crate) fn total_generation(
+    matches: &[Match],
+    paths: &FilePaths,
+) -> BTreeMap<(String, String), f64> {
+    let manual_fuels: BTreeMap<_, _> = matches
+        .iter()
+        .filter(|m| m.has_manual_fuel())
+        .flat_map(|m| m.generation.iter().map(|g| (g.eic.as_str(), m.fuel.as_deref().unwrap())))
+        .collect();
+
+    let mut total_generation = BTreeMap::new();
     let mut csv_reader = load_csv_file(&paths.generation_file(), ',');
     for result in csv_reader.deserialize() {
         let csv_gen: YearlyGeneration = result.unwrap();
 
-        if csv_gen.fuel != "other" {
-            let key = (csv_gen.country.to_string(), csv_gen.fuel.to_string());
-            fuel_stats.entry(key).or_default().total_generation += csv_gen.output;
+        let fuel = manual_fuels.get(csv_gen.eic.as_str()).copied().unwrap_or(&csv_gen.fuel);
+        if fuel != "other" {
+            let key = (csv_gen.country.to_string(), fuel.to_string());
+            *total_generation.entry(key).or_default() += csv_gen.output;
         }
     }
 
+    total_generation
+}
+
+fn country_stats(matches: &[Match], paths: &FilePaths) -> BTreeMap<(String, String), FuelStats> {
+    let mut fuel_stats = BTreeMap::<_, FuelStats>::new();
+
+    // all relevant generation (even unmatched), to calculate coverage
+    for (key, generation) in total_generation(matches, paths) {
+        fuel_stats.entry(key).or_default().total_generation = generation;
+    }
+
     // include all valid matches in country-level stats
     for m in matches.iter().filter(|m| !m.is_ignored()) {
-        let key = (m.country.to_string(), m.fuel.as_ref().unwrap().to_string());
-        fuel_stats.get_mut(&key).unwrap().add_match(m);
+        for share in &m.fuel_shares {
+            let key = (m.country.to_string(), share.fuel.clone());
+            fuel_stats.get_mut(&key).unwrap().add_match(m, share);
+        }
     }
 
     // create additional stats for country "" (sums up all countries)
@@ -453,11 +788,9 @@ fn generate_output(matches: &mut [Match], paths: &FilePaths) {
     }
     fuel_stats.append(&mut coal_lignite_stats);
 
-    // write country-level stats
-    let mut csv_writer = Writer::from_path(paths.out_countries_file()).unwrap();
-    for ((country, fuel), mut stat) in fuel_stats {
-        stat.country = country;
-        stat.fuel = fuel;
+    for ((country, fuel), stat) in &mut fuel_stats {
+        stat.country = country.clone();
+        stat.fuel = fuel.clone();
         stat.coverage_percentage = if stat.total_generation > 0.0 {
             (100.0 * stat.matched_generation) / stat.total_generation
         } else {
@@ -466,11 +799,33 @@ fn generate_output(matches: &mut [Match], paths: &FilePaths) {
 
         if stat.matched_generation > 0.0 {
             stat.emission_factor = Some((1000.0 * stat.emissions_el) / stat.matched_generation);
-        }
+            let fossil_generation = stat.matched_generation - stat.matched_generation_biogenic;
+            stat.emission_factor_fossil = Some((1000.0 * stat.emissions_el) / fossil_generation);
+            let emissions_el = stat.emissions_el + stat.emissions_el_biogenic;
+            stat.emission_factor_incl_biogenic =
+                Some((1000.0 * emissions_el) / stat.matched_generation);
+
+            if let Some(non_co2) = stat.emissions_el_non_co2 {
+                let emissions_co2eq = stat.emissions_el + non_co2;
+                stat.emission_factor_co2eq =
+                    Some((1000.0 * emissions_co2eq) / stat.matched_generation);
+                stat.emission_factor_lifecycle = stat.emissions_el_upstream.map(|upstream| {
+                    (1000.0 * (emissions_co2eq + upstream)) / stat.matched_generation
+                });
+            }
 
-        csv_writer.serialize(stat).unwrap();
+            if !stat.emissions_el_samples.is_empty() {
+                let samples = stat.emissions_el_samples.iter();
+                let emission_factors = samples.map(|e| (1000.0 * e) / stat.matched_generation);
+                let (p5, p50, p95) = percentiles(emission_factors.collect());
+                stat.emission_factor_p5 = Some(p5);
+                stat.emission_factor_p50 = Some(p50);
+                stat.emission_factor_p95 = Some(p95);
+            }
+        }
     }
-    csv_writer.flush().unwrap();
+
+    fuel_stats
 }
 
 fn load_csv_file(path: &Path, separator: char) -> Reader<File> {
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+    path::Path,
+};
+
+use csv::{ReaderBuilder, Trim};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    FilePaths,
+    generation_emission_match::{Match, YearlyEmission, YearlyGeneration, fuel_co2_intensity},
+    load_csv_file,
+};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ManualMatch {
+    pub(crate) generation: String,
+    pub(crate) emission: String,
+    pub(crate) settings: String,
+    pub(crate) comment: String,
+    #[serde(default)]
+    pub(crate) from_year: Option<u32>,
+    #[serde(default)]
+    pub(crate) to_year: Option<u32>,
+
+    /// File name and line number, for error messages
+    #[serde(skip)]
+    pub(crate) location: String,
+}
+
+impl ManualMatch {
+    pub(crate) fn generation_names(&self) -> impl Iterator<Item = &str> {
+        self.generation.split('|').filter(|name| !name.is_empty())
+    }
+
+    pub(crate) fn emission_names(&self) -> impl Iterator<Item = &str> {
+        self.emission.split('|').filter(|name| !name.is_empty())
+    }
+
+    /// Lines without generation and emission are only used for comments.
+    pub(crate) fn is_comment(&self) -> bool {
+        self.generation.is_empty() && self.emission.is_empty()
+    }
+
+    fn is_valid_for(&self, year: u32) -> bool {
+        self.from_year.is_none_or(|from| from <= year) && self.to_year.is_none_or(|to| year <= to)
+    }
+}
+
+/// A generation reference that may resolve to several generation units of one country
+pub(crate) enum GenerationPattern {
+    /// `regex:[country]:[regex]`, matching whole generation unit names
+    Regex { country: String, regex: Regex },
+    /// `eic-prefix:[country]:[prefix]`
+    EicPrefix { country: String, prefix: String },
+}
+
+impl GenerationPattern {
+    /// Returns `None` if the reference is a plain name or EIC reference.
+    pub(crate) fn parse(reference: &str) -> Option<Result<Self, String>> {
+        let (kind, pattern) = reference.split_once(':')?;
+        if kind != "regex" && kind != "eic-prefix" {
+            return None;
+        }
+
+        let Some((country, pattern)) = pattern.split_once(':') else {
+            return Some(Err(format!("bad generation pattern \"{reference}\", country missing")));
+        };
+        let country = country.to_string();
+
+        Some(match kind {
+            "regex" => Regex::new(&format!("^(?:{pattern})$"))
+                .map(|regex| Self::Regex { country, regex })
+                .map_err(|e| format!("bad regex in \"{reference}\": {e}")),
+            _ => Ok(Self::EicPrefix { country, prefix: pattern.to_string() }),
+        })
+    }
+
+    pub(crate) fn matches(&self, generation: &YearlyGeneration) -> bool {
+        match self {
+            Self::Regex { country, regex } => {
+                generation.country == *country && regex.is_match(&generation.name)
+            }
+            Self::EicPrefix { country, prefix } => {
+                generation.country == *country && generation.eic.starts_with(prefix)
+            }
+        }
+    }
+}
+
+/// A per-match setting from the `settings` column of manual_matches.csv
+pub(crate) enum MatchSetting {
+    PlausibleEmissionFactorRange(Range<f64>),
+    EmissionShare(f64),
+    FuelInput(f64),
+    Fuel(String),
+    Sigma(f64),
+    EfficiencyHeat(f64),
+    EfficiencyEl(f64),
+    HeatOutput(f64),
+    CofiringShare(f64),
+}
+
+impl MatchSetting {
+    pub(crate) fn parse(setting: &str) -> Result<Self, String> {
+        let (key, val) = setting.split_once(':').ok_or_else(|| format!("bad setting {setting}"))?;
+
+        match key {
+            "plausible-emission-factor-range" => {
+                let (min_s, max_s) = val
+                    .split_once('-')
+                    .ok_or_else(|| format!("bad emission factor range {val}"))?;
+                let min = min_s
+                    .parse()
+                    .map_err(|_| format!("bad minimum plausible emission factor {min_s}"))?;
+                let max = max_s
+                    .parse()
+                    .map_err(|_| format!("bad maximum plausible emission factor {max_s}"))?;
+                Ok(Self::PlausibleEmissionFactorRange(min..max))
+            }
+            "emission-share" => Ok(Self::EmissionShare(parse_fraction(key, val)?)),
+            "fuel-input" => match val.parse() {
+                Ok(mwh) if mwh > 0.0 => Ok(Self::FuelInput(mwh)),
+                _ => Err(format!("bad fuel input {val}, must be a positive number of MWh")),
+            },
+            "fuel" => match val {
+                "gas" | "coal" | "lignite" | "oil" | "other" => Ok(Self::Fuel(val.to_string())),
+                _ => Err(format!("unknown fuel {val}")),
+            },
+            "sigma" => Ok(Self::Sigma(parse_fraction(key, val)?)),
+            "efficiency-heat" => Ok(Self::EfficiencyHeat(parse_efficiency(key, val)?)),
+            "efficiency-el" => Ok(Self::EfficiencyEl(parse_efficiency(key, val)?)),
+            "heat-output" => match val.parse() {
+                Ok(mwh) if mwh >= 0.0 && f64::is_finite(mwh) => Ok(Self::HeatOutput(mwh)),
+                _ => {
+                    Err(format!("bad heat output {val}, must be a finite number of at least 0 MWh"))
+                }
+            },
+            "cofiring-share" => match val.parse() {
+                Ok(share) if (0.0..1.0).contains(&share) => Ok(Self::CofiringShare(share)),
+                _ => Err(format!("bad {key} {val}, must be at least 0 and below 1")),
+            },
+            _ => Err(format!("invalid setting {key}:{val}")),
+        }
+    }
+
+    /// Settings that replace values which are usually derived from the input data
+    fn is_override(&self) -> bool {
+        match self {
+            Self::PlausibleEmissionFactorRange(_) | Self::EmissionShare(_) | Self::FuelInput(_) => {
+                false
+            }
+            Self::Fuel(_)
+            | Self::Sigma(_)
+            | Self::EfficiencyHeat(_)
+            | Self::EfficiencyEl(_)
+            | Self::HeatOutput(_)
+            | Self::CofiringShare(_) => true,
+        }
+    }
+
+    pub(crate) fn apply(self, m: &mut Match) -> Result<(), String> {
+        match self {
+            Self::PlausibleEmissionFactorRange(range) => m.plausible_emission_factor = Some(range),
+            Self::EmissionShare(share) => m.set_emission_share(share),
+            Self::FuelInput(mwh) => {
+                let fuel = m.fuel.as_deref().unwrap_or("mixed");
+                let intensity = fuel_co2_intensity(fuel)
+                    .ok_or_else(|| format!("fuel-input setting unsupported for fuel {fuel}"))?;
+                let emission_sum: f64 = m.emission.iter().map(|e| e.emissions).sum();
+                if emission_sum > 0.0 {
+                    // 3.6 GJ in one MWh
+                    let fuel_emissions = mwh * 0.0036 * intensity;
+                    m.set_emission_share((fuel_emissions / emission_sum).min(1.0));
+                }
+            }
+            Self::Fuel(fuel) => m.fuel = Some(fuel),
+            Self::Sigma(sigma) => m.sigma = sigma,
+            Self::EfficiencyHeat(efficiency) => m.efficiency_heat = Some(efficiency),
+            Self::EfficiencyEl(efficiency) => m.efficiency_el = Some(efficiency),
+            Self::HeatOutput(mwh) => m.heat_output = Some(mwh),
+            Self::CofiringShare(share) => m.cofiring_share = Some(share),
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_fraction(key: &str, val: &str) -> Result<f64, String> {
+    match val.parse() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("bad {key} {val}, must be between 0 and 1")),
+    }
+}
+
+/// Efficiencies are divided by, so they must be above 0
+fn parse_efficiency(key: &str, val: &str) -> Result<f64, String> {
+    match val.parse() {
+        Ok(efficiency) if efficiency > 0.0 && efficiency <= 1.0 => Ok(efficiency),
+        _ => Err(format!("bad {key} {val}, must be above 0 and at most 1")),
+    }
+}
+
+pub(crate) fn parse_settings(settings: &str) -> impl Iterator<Item = Result<MatchSetting, String>> {
+    settings.split_terminator('|').map(MatchSetting::parse)
+}
+
+/// Checks for settings that can't be used together on one line
+pub(crate) fn check_settings<'a>(
+    settings: impl IntoIterator<Item = &'a MatchSetting>,
+) -> Result<(), String> {
+    let emission_shares = settings
+        .into_iter()
+        .filter(|setting| {
+            matches!(setting, MatchSetting::EmissionShare(_) | MatchSetting::FuelInput(_))
+        })
+        .count();
+    if emission_shares > 1 {
+        return Err("only one emission-share or fuel-input setting allowed".to_string());
+    }
+
+    Ok(())
+}
+
+/// Reads all lines of a manual matches file. Lines may omit the optional trailing columns.
+fn read_manual_matches(path: &Path) -> Vec<ManualMatch> {
+    let mut csv_reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_path(path)
+        .unwrap_or_else(|e| panic!("Failed to load {path:?}: {e:?}"));
+    let headers = csv_reader.headers().unwrap().clone();
+
+    csv_reader
+        .records()
+        .map(|result| {
+            let record = result.expect("badly formated manual_matches.csv file!");
+            let location = format!("{}:{}", path.display(), record.position().unwrap().line());
+            let mut m: ManualMatch = record
+                .deserialize(Some(&headers))
+                .unwrap_or_else(|e| panic!("badly formated manual matches file, {location}: {e}"));
+            m.location = location;
+            m
+        })
+        .collect()
+}
+
+/// Generation units (EICs) that the generation references of a line resolve to, unresolvable
+/// references are left out
+fn resolve_units(m: &ManualMatch, generation: &[YearlyGeneration]) -> BTreeSet<String> {
+    let mut units = BTreeSet::new();
+    for name in m.generation_names() {
+        let pattern = GenerationPattern::parse(name).and_then(Result::ok);
+        let matches = |g: &YearlyGeneration| match &pattern {
+            Some(pattern) => pattern.matches(g),
+            None => g.name == name || name.strip_prefix("eic:") == Some(&g.eic),
+        };
+        units.extend(generation.iter().filter(|g| matches(g)).map(|g| g.eic.clone()));
+    }
+    units
+}
+
+/// Merges the shared manual matches file (lines valid for the given year) with the per-year file.
+/// Shared lines are replaced by per-year lines that use any of their generation units, whether
+/// they refer to them by name, EIC or pattern.
+pub(crate) fn manual_matches_for_year(paths: &FilePaths) -> Vec<ManualMatch> {
+    let shared_file = paths.shared_manual_matches_file();
+    let year_file = paths.manual_matches_file();
+    assert!(
+        shared_file.exists() || year_file.exists(),
+        "Neither {shared_file:?} nor {year_file:?} found"
+    );
+
+    let year_matches =
+        if year_file.exists() { read_manual_matches(&year_file) } else { Vec::new() };
+    let shared_matches =
+        if shared_file.exists() { read_manual_matches(&shared_file) } else { Vec::new() };
+
+    let generation: Vec<YearlyGeneration> =
+        load_csv_file(&paths.generation_file(), ',').deserialize().map(Result::unwrap).collect();
+    let year_matches: Vec<_> =
+        year_matches.into_iter().filter(|m| m.is_valid_for(paths.year())).collect();
+    // unresolvable references are compared by name
+    let overridden_names: BTreeSet<_> =
+        year_matches.iter().flat_map(|m| m.generation_names()).map(str::to_string).collect();
+    let overridden_units: BTreeSet<_> =
+        year_matches.iter().flat_map(|m| resolve_units(m, &generation)).collect();
+
+    shared_matches
+        .into_iter()
+        .filter(|m| m.is_valid_for(paths.year()))
+        .filter(|m| m.generation_names().all(|name| !overridden_names.contains(name)))
+        .filter(|m| resolve_units(m, &generation).is_disjoint(&overridden_units))
+        .chain(year_matches)
+        .collect()
+}
+
+pub(crate) fn load_manual_matches(out: &mut Vec<Match>, paths: &FilePaths) {
+    let mut manual_matches = Vec::new();
+    let mut load_generation = BTreeMap::<String, Option<YearlyGeneration>>::new();
+    let mut load_patterns = BTreeMap::<String, (GenerationPattern, Vec<YearlyGeneration>)>::new();
+    let mut load_emission = BTreeMap::<String, Option<YearlyEmission>>::new();
+
+    for m in manual_matches_for_year(paths) {
+        for name in m.generation.split('|') {
+            match GenerationPattern::parse(name) {
+                Some(pattern) => {
+                    let pattern = pattern.unwrap_or_else(|e| panic!("{}: {e}", m.location));
+                    load_patterns.insert(name.to_string(), (pattern, Vec::new()));
+                }
+                None => {
+                    load_generation.insert(name.to_string(), None);
+                }
+            }
+        }
+        load_emission.extend(m.emission.split('|').map(|name| (name.to_string(), None)));
+
+        manual_matches.push(m);
+    }
+
+    let mut csv_reader = load_csv_file(&paths.generation_file(), ',');
+    for result in csv_reader.deserialize() {
+        let csv_gen: YearlyGeneration = result.unwrap();
+
+        let mut generation = load_generation.get_mut(&csv_gen.name);
+        if generation.is_none() {
+            generation = load_generation.get_mut(&format!("eic:{}", csv_gen.eic));
+        }
+
+        let mut matching_patterns =
+            load_patterns.iter_mut().filter(|(_, (pattern, _))| pattern.matches(&csv_gen));
+        let pattern = matching_patterns.next();
+        if let Some((pattern_name, _)) = &pattern {
+            let name = &csv_gen.name;
+            assert!(generation.is_none(), "\"{pattern_name}\" captures \"{name}\", used elsewhere");
+            if let Some((other_name, _)) = matching_patterns.next() {
+                panic!("\"{pattern_name}\" and \"{other_name}\" both capture \"{name}\"");
+            }
+        }
+
+        if let Some(generation) = generation {
+            assert!(generation.is_none(), "Found two generation units: \"{}\"", csv_gen.name);
+            *generation = Some(csv_gen);
+        } else if let Some((_, (_, units))) = pattern {
+            units.push(csv_gen);
+        }
+    }
+
+    for (pattern_name, (_, units)) in &load_patterns {
+        assert!(!units.is_empty(), "\"{pattern_name}\" matches no generation units");
+        let unit_names = units.iter().map(|g| g.name.as_str()).collect::<Vec<_>>().join("|");
+        println!("Info: \"{pattern_name}\" resolved to {unit_names}");
+    }
+
+    let mut csv_reader = load_csv_file(&paths.emissions_file(), ',');
+    for result in csv_reader.deserialize() {
+        let em: YearlyEmission = result.unwrap();
+
+        let mut emission = load_emission.get_mut(&em.name);
+        if emission.is_none() {
+            emission = load_emission.get_mut(&format!("id:{}", em.id));
+        }
+
+        if let Some(emission) = emission {
+            assert!(emission.is_none(), "Found two ETS emission records: \"{}\"", em.name);
+            *emission = Some(em);
+        }
+    }
+
+    for manual_match in manual_matches {
+        if manual_match.is_comment() {
+            // so a CSV comment line can be inserted like ",,,DE"
+            continue;
+        }
+
+        let mut m = Match::new(
+            String::from("Manual Match"),
+            manual_match
+                .generation
+                .split('|')
+                .flat_map(|name| {
+                    if let Some((_, units)) = load_patterns.remove(name) {
+                        return units;
+                    }
+                    let generation = load_generation
+                        .remove(name)
+                        .unwrap_or_else(|| panic!("generation \"{name}\" used more than once."))
+                        .unwrap_or_else(|| panic!("generation \"{name}\" not found."));
+                    vec![generation]
+                })
+                .collect(),
+            manual_match
+                .emission_names()
+                .map(|name| {
+                    load_emission
+                        .remove(name)
+                        .unwrap_or_else(|| panic!("emission \"{name}\" used more than once."))
+                        .unwrap_or_else(|| panic!("emission \"{name}\" not found."))
+                })
+                .collect(),
+        );
+
+        if manual_match.emission.is_empty() {
+            m.ignore(format!("filtered in manual_matches.csv: {}", manual_match.comment));
+        }
+
+        let mut settings: Vec<_> = manual_match
+            .settings
+            .split_terminator('|')
+            .zip(parse_settings(&manual_match.settings))
+            .map(|(setting_str, setting)| {
+                (setting_str, setting.unwrap_or_else(|e| panic!("{}: {e}", manual_match.location)))
+            })
+            .collect();
+        check_settings(settings.iter().map(|(_, setting)| setting))
+            .unwrap_or_else(|e| panic!("{}: {e}", manual_match.location));
+        // the fuel is set first, other settings like fuel-input depend on it
+        settings.sort_by_key(|(_, setting)| !matches!(setting, MatchSetting::Fuel(_)));
+
+        for (setting_str, setting) in settings {
+            if setting.is_override() {
+                m.overrides.push(setting_str.to_string());
+            }
+            setting.apply(&mut m).unwrap_or_else(|e| panic!("{}: {e}", manual_match.location));
+        }
+
+        out.push(m);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use deunicode::deunicode;
+use serde::Deserialize;
+
+use crate::{
+    FilePaths,
+    country_codes::{CountryCodes, Dataset},
+    generation_emission_match::{YearlyEmission, YearlyGeneration},
+    load_csv_file,
+};
+
+#[derive(Deserialize)]
+struct StopWordRow {
+    country: String,
+    word: String,
+}
+
+/// Words that are ignored when deriving a key from a generation unit or ETS installation name,
+/// loaded from "data/stop_words.csv". Words without a country apply to all countries.
+pub(crate) struct StopWords {
+    words: Vec<(String, String)>,
+}
+
+impl StopWords {
+    pub(crate) fn load(paths: &FilePaths) -> Self {
+        let words = load_csv_file(&paths.stop_words_file(), ',')
+            .deserialize()
+            .map(|result| {
+                let row: StopWordRow = result.unwrap();
+                (row.country, deunicode(&row.word.to_lowercase()))
+            })
+            .collect();
+
+        Self { words }
+    }
+
+    fn is_stop_word(&self, country: &str, part: &str) -> bool {
+        self.words.iter().any(|(c, word)| (c.is_empty() || c == country) && part.contains(word))
+    }
+
+    fn parts(&self, country: &str, name: &str) -> Vec<String> {
+        deunicode(&name.to_lowercase())
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|part| !self.is_stop_word(country, part))
+            .filter(|part| part.len() >= 3)
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The longest word of a name, ignoring stop words and very short words. Generation units and
+    /// ETS installations of the same country that share a key are matched automatically.
+    pub(crate) fn key(&self, country: &str, name: &str) -> String {
+        self.parts(country, name).into_iter().max_by_key(|part| part.len()).unwrap_or_default()
+    }
+}
+
+/// Finds words that collapse several unrelated names onto the same key, and prints them as stop
+/// word suggestions. The names of a key group are considered unrelated if ignoring their key word
+/// splits them up into at least three different keys.
+pub(crate) fn suggest_stop_words(paths: &FilePaths) {
+    let stop_words = StopWords::load(paths);
+    let country_codes = CountryCodes::load(paths);
+
+    let mut names = BTreeSet::new();
+    for result in load_csv_file(&paths.generation_file(), ',').deserialize() {
+        let csv_gen: YearlyGeneration = result.unwrap();
+        names.insert((csv_gen.country, csv_gen.name));
+    }
+    for result in load_csv_file(&paths.emissions_file(), ',').deserialize() {
+        let em: YearlyEmission = result.unwrap();
+        names.insert((country_codes.normalize(Dataset::Ets, &em.country), em.name));
+    }
+
+    let mut groups = BTreeMap::<(&str, String), Vec<&str>>::new();
+    let mut frequency = BTreeMap::<String, usize>::new();
+    for (country, name) in &names {
+        let parts = stop_words.parts(country, name);
+        for part in parts.iter().collect::<BTreeSet<_>>() {
+            *frequency.entry(part.clone()).or_default() += 1;
+        }
+
+        let key = parts.into_iter().max_by_key(|part| part.len()).unwrap_or_default();
+        if !key.is_empty() {
+            groups.entry((country.as_str(), key)).or_default().push(name);
+        }
+    }
+
+    #[derive(Default)]
+    struct Suggestion<'a> {
+        collapsed_names: usize,
+        countries: BTreeSet<&'a str>,
+        examples: Vec<&'a str>,
+    }
+
+    let mut suggestions = BTreeMap::<&str, Suggestion>::new();
+    for ((country, key), group) in &groups {
+        let alternative_keys: BTreeSet<_> = group
+            .iter()
+            .filter_map(|name| {
+                let parts = stop_words.parts(country, name);
+                parts.into_iter().filter(|part| part != key).max_by_key(|part| part.len())
+            })
+            .collect();
+
+        if alternative_keys.len() >= 3 {
+            let suggestion = suggestions.entry(key).or_default();
+            suggestion.collapsed_names += group.len();
+            suggestion.countries.insert(country);
+            suggestion.examples.extend(group.iter().take(3));
+        }
+    }
+
+    let mut suggestions: Vec<_> = suggestions.into_iter().collect();
+    suggestions.sort_by_key(|(_, s)| std::cmp::Reverse(s.collapsed_names));
+
+    for (word, suggestion) in suggestions {
+        let countries = suggestion.countries.into_iter().collect::<Vec<_>>().join(",");
+        println!(
+            "{word}: collapses {} names in {countries}, used in {} names, e.g. \"{}\"",
+            suggestion.collapsed_names,
+            frequency[word],
+            suggestion.examples.join("\", \""),
+        );
+    }
+}
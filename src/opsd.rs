@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::{
+    FilePaths,
+    country_codes::{CountryCodes, Dataset},
+    generation_emission_match::{Match, Provenance, YearlyEmission, YearlyGeneration},
+    load_csv_file,
+};
+
+/// All columns are required, so a copy with a different schema isn't silently ignored
+#[derive(Deserialize)]
+struct OpsdPlant {
+    name_bnetza: String,
+    eic_code_block: String,
+    eic_code_plant: String,
+    ets_id: String,
+}
+
+/// EICs and ETS IDs that are linked by OPSD, directly or through shared blocks / installations
+struct Link {
+    name: String,
+    eics: BTreeSet<String>,
+    ets_ids: BTreeSet<String>,
+}
+
+/// Several EICs or ETS IDs may be listed in one field
+fn split_ids(ids: &str) -> impl Iterator<Item = String> + '_ {
+    ids.split([',', ';', '|', ' ']).filter(|id| !id.is_empty()).map(str::to_string)
+}
+
+/// Adds matches for the EIC <-> ETS installation links of the Open Power System Data conventional
+/// power plant list for Germany, if a copy of it exists in "data/opsd". Only German generation
+/// units and ETS records are linked. Generation units and ETS records used by manual matches are
+/// left out, and links that contradict a manual match are reported. This needs to run after
+/// loading manual matches and before automatic matching.
+pub(crate) fn load_external_matches(matches: &mut Vec<Match>, paths: &FilePaths) {
+    let opsd_file = paths.opsd_plants_file();
+    if !opsd_file.exists() {
+        return;
+    }
+
+    let mut links: Vec<Link> = Vec::new();
+    for result in load_csv_file(&opsd_file, ',').deserialize() {
+        let plant: OpsdPlant = result.unwrap_or_else(|e| panic!("{opsd_file:?}: {e}"));
+        let eics = if plant.eic_code_block.is_empty() {
+            &plant.eic_code_plant
+        } else {
+            &plant.eic_code_block
+        };
+        let mut link = Link {
+            name: plant.name_bnetza,
+            eics: split_ids(eics).collect(),
+            ets_ids: split_ids(&plant.ets_id).collect(),
+        };
+        if link.eics.is_empty() || link.ets_ids.is_empty() {
+            continue;
+        }
+
+        // merge with all links sharing a block or an installation
+        let (connected, others): (Vec<_>, Vec<_>) = links.into_iter().partition(|other| {
+            !other.eics.is_disjoint(&link.eics) || !other.ets_ids.is_disjoint(&link.ets_ids)
+        });
+        for other in connected {
+            link.name = other.name;
+            link.eics.extend(other.eics);
+            link.ets_ids.extend(other.ets_ids);
+        }
+        links = others;
+        links.push(link);
+    }
+
+    // generation unit / ETS record -> ETS records / generation units it was manually matched with
+    let mut manual_generation = BTreeMap::<String, BTreeSet<String>>::new();
+    let mut manual_emission = BTreeMap::<String, BTreeSet<String>>::new();
+    for m in matches.iter() {
+        for generation in &m.generation {
+            let ids = m.emission.iter().map(|e| e.id.clone());
+            manual_generation.entry(generation.eic.clone()).or_default().extend(ids);
+        }
+        for emission in &m.emission {
+            let eics = m.generation.iter().map(|g| g.eic.clone());
+            manual_emission.entry(emission.id.clone()).or_default().extend(eics);
+        }
+    }
+
+    let mut generation = BTreeMap::<String, YearlyGeneration>::new();
+    for result in load_csv_file(&paths.generation_file(), ',').deserialize() {
+        let csv_gen: YearlyGeneration = result.unwrap();
+        if csv_gen.country == "DE" {
+            generation.insert(csv_gen.eic.clone(), csv_gen);
+        }
+    }
+
+    // OPSD may list just the installation identifier instead of "[permit_id]:[installation_id]",
+    // which is only unique within a country
+    let country_codes = CountryCodes::load(paths);
+    let mut emission = BTreeMap::<String, YearlyEmission>::new();
+    let mut ambiguous = BTreeMap::<String, BTreeSet<String>>::new();
+    for result in load_csv_file(&paths.emissions_file(), ',').deserialize() {
+        let mut em: YearlyEmission = result.unwrap();
+        em.country = country_codes.normalize(Dataset::Ets, &em.country);
+        if em.country != "DE" {
+            continue;
+        }
+
+        let installation_id = em.id.split_once(':').map(|(_, id)| id.to_string());
+        for key in installation_id.into_iter().chain([em.id.clone()]) {
+            if let Some(other) = emission.insert(key.clone(), em.clone())
+                && other.id != em.id
+            {
+                ambiguous.entry(key).or_default().extend([other.id, em.id.clone()]);
+            }
+        }
+    }
+
+    let (mut added, mut conflicts) = (0, 0);
+    for link in links {
+        if let Some((id, ids)) = link.ets_ids.iter().find_map(|id| ambiguous.get_key_value(id)) {
+            panic!(
+                "OPSD links \"{}\" to \"{id}\", which matches several ETS records: {}",
+                link.name,
+                ids.iter().cloned().collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        let mut link_generation: Vec<_> =
+            link.eics.iter().filter_map(|eic| generation.remove(eic)).collect();
+        let mut link_emission: Vec<_> = link
+            .ets_ids
+            .iter()
+            .filter_map(|id| emission.get(id))
+            .map(|em| (em.id.clone(), em.clone()))
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect();
+
+        // manual matches take precedence, but should agree with OPSD
+        let link_ids: BTreeSet<_> = link_emission.iter().map(|e| e.id.clone()).collect();
+        let link_eics: BTreeSet<_> = link_generation.iter().map(|g| g.eic.clone()).collect();
+        let conflicting_generation = link_generation.iter().filter(|g| {
+            manual_generation.get(&g.eic).is_some_and(|ids| !ids.is_empty() && ids != &link_ids)
+        });
+        let conflicting_emission = link_emission.iter().filter(|e| {
+            manual_emission.get(&e.id).is_some_and(|eics| eics.is_disjoint(&link_eics))
+        });
+        let conflicting: Vec<_> = conflicting_generation
+            .map(|g| g.name.as_str())
+            .chain(conflicting_emission.map(|e| e.name.as_str()))
+            .collect();
+        if !conflicting.is_empty() {
+            println!(
+                "Info: OPSD links \"{}\" differently than manual matches: {}",
+                link.name,
+                conflicting.join(", ")
+            );
+            conflicts += 1;
+        }
+
+        link_generation.retain(|g| !manual_generation.contains_key(&g.eic));
+        link_emission.retain(|e| !manual_emission.contains_key(&e.id));
+        if link_generation.is_empty() || link_emission.is_empty() {
+            continue;
+        }
+
+        for em in &link_emission {
+            emission.retain(|_, other| other.id != em.id);
+        }
+        let name = if link.name.is_empty() { link_emission[0].name.clone() } else { link.name };
+        let mut m = Match::new(name, link_generation, link_emission);
+        m.provenance = Provenance::External;
+        matches.push(m);
+        added += 1;
+    }
+
+    println!("Info: Added {added} matches from {opsd_file:?}, {conflicts} conflicting links");
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use crate::generation_emission_match::Match;
+
+/// Distributions of fewer plants are too uncertain to find outliers
+const MIN_PLANTS: usize = 5;
+/// Robust z-scores above this are outliers (Iglewicz and Hoaglin)
+const MAX_ROBUST_Z: f64 = 3.5;
+/// Scales the median absolute deviation to the standard deviation of a normal distribution
+const MAD_SCALE: f64 = 0.6745;
+
+/// Median and median absolute deviation
+fn median_mad(mut values: Vec<f64>) -> (f64, f64) {
+    let median = |values: &mut Vec<f64>| {
+        values.sort_by(f64::total_cmp);
+        let mid = values.len() / 2;
+        if values.len().is_multiple_of(2) {
+            (values[mid - 1] + values[mid]) / 2.0
+        } else {
+            values[mid]
+        }
+    };
+
+    let med = median(&mut values);
+    let mut deviations: Vec<_> = values.iter().map(|value| (value - med).abs()).collect();
+    (med, median(&mut deviations))
+}
+
+/// Ignores plants whose emission factor is far off the ones of other plants with the same fuel,
+/// using robust z-scores based on the median absolute deviation. Plants are compared to their
+/// country if it has enough plants with that fuel, otherwise to all countries. Plants with a
+/// manually set plausible emission factor range are only subject to that range, and mixed-fuel
+/// plants aren't compared to others.
+pub(crate) fn ignore_outliers(matches: &mut [Match]) {
+    let checked = |m: &Match| {
+        !m.is_ignored() && m.plausible_emission_factor.is_none() && m.fuel_shares.len() == 1
+    };
+
+    let mut distributions = BTreeMap::<_, Vec<f64>>::new();
+    for m in matches.iter().filter(|m| checked(m)) {
+        let fuel = m.fuel.clone().unwrap();
+        distributions
+            .entry((m.country.clone(), fuel.clone()))
+            .or_default()
+            .push(m.emission_factor_fossil);
+        distributions.entry((String::new(), fuel)).or_default().push(m.emission_factor_fossil);
+    }
+    let distributions: BTreeMap<_, _> = distributions
+        .into_iter()
+        .filter(|(_, values)| values.len() >= MIN_PLANTS)
+        .map(|(key, values)| (key, median_mad(values)))
+        .filter(|(_, (_, mad))| *mad > 0.0)
+        .collect();
+
+    for m in matches.iter_mut().filter(|m| checked(m)) {
+        let fuel = m.fuel.clone().unwrap();
+        let country_key = (m.country.clone(), fuel.clone());
+        let Some(((country, _), (median, mad))) = distributions
+            .get_key_value(&country_key)
+            .or_else(|| distributions.get_key_value(&(String::new(), fuel.clone())))
+        else {
+            continue;
+        };
+
+        let z = MAD_SCALE * (m.emission_factor_fossil - median) / mad;
+        if z.abs() > MAX_ROBUST_Z {
+            let half_width = MAX_ROBUST_Z * mad / MAD_SCALE;
+            let scope = if country.is_empty() { "all countries" } else { country };
+            m.ignore(format!(
+                "emission factor {:.0} is an outlier, robust z-score {z:.1}, expected {:.0}-{:.0} \
+                 for {fuel} in {scope}",
+                m.emission_factor_fossil,
+                median - half_width,
+                median + half_width
+            ));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+
+use crate::{FilePaths, generation_emission_match::Match, load_csv_file};
+
+#[derive(Deserialize, Serialize)]
+struct RegistryEntry {
+    id: String,
+    /// name of the match when the ID was assigned, for orientation only
+    name: String,
+    eics: String,
+    ets_ids: String,
+}
+
+/// Assigns stable IDs to all matches, based on "data/plant_registry.csv". A plant is identified by
+/// the EICs of its generation units and the IDs of its ETS installations, so it keeps its ID when
+/// units or installations are renamed. A match gets the ID of the registry entry that shares the
+/// most EICs and ETS IDs with it, or a new ID if there is none. New EICs and ETS IDs are added to
+/// the entry, and the registry file is updated.
+pub(crate) fn assign_plant_ids(matches: &mut [Match], paths: &FilePaths) {
+    let registry_file = paths.plant_registry_file();
+    let mut registry: BTreeMap<String, (String, BTreeSet<String>)> = BTreeMap::new();
+    if registry_file.exists() {
+        for result in load_csv_file(&registry_file, ',').deserialize() {
+            let entry: RegistryEntry = result.unwrap();
+            let references = split(&entry.eics).map(|eic| format!("eic:{eic}"));
+            let references = references.chain(split(&entry.ets_ids).map(|id| format!("id:{id}")));
+            registry.insert(entry.id, (entry.name, references.collect()));
+        }
+    }
+
+    let mut owner = BTreeMap::<String, String>::new();
+    for (id, (_, references)) in &registry {
+        for reference in references {
+            if let Some(other) = owner.insert(reference.clone(), id.clone()) {
+                panic!(
+                    "\"{reference}\" is listed for plants {other} and {id} in {registry_file:?}"
+                );
+            }
+        }
+    }
+
+    // largest plants first, so they keep their ID if a plant was split up
+    let mut order: Vec<_> = (0..matches.len()).collect();
+    order.sort_by(|&x, &y| matches[y].generation_el.total_cmp(&matches[x].generation_el));
+
+    let mut last_id = registry
+        .keys()
+        .map(|id| {
+            id.strip_prefix('P').and_then(|number| number.parse::<u32>().ok()).unwrap_or_else(
+                || panic!("bad plant ID \"{id}\" in {registry_file:?}, must be P and a number"),
+            )
+        })
+        .max()
+        .unwrap_or(0);
+    let mut assigned = BTreeSet::new();
+    for i in order {
+        let m = &mut matches[i];
+        let references: BTreeSet<_> = m
+            .generation
+            .iter()
+            .map(|g| format!("eic:{}", g.eic))
+            .chain(m.emission.iter().map(|e| format!("id:{}", e.id)))
+            .collect();
+
+        let mut overlap = BTreeMap::<&String, usize>::new();
+        for id in references.iter().filter_map(|reference| owner.get(reference)) {
+            *overlap.entry(id).or_default() += 1;
+        }
+        let best = overlap
+            .into_iter()
+            .filter(|(id, _)| !assigned.contains(*id))
+            .max_by_key(|&(id, count)| (count, std::cmp::Reverse(id)))
+            .map(|(id, _)| id.clone());
+
+        let id = best.unwrap_or_else(|| {
+            last_id += 1;
+            let id = format!("P{last_id:05}");
+            let name = m.emission.first().map_or(&m.generation[0].name, |e| &e.name);
+            registry.insert(id.clone(), (name.clone(), BTreeSet::new()));
+            id
+        });
+
+        // references stay with the plant they were first registered for
+        for reference in references {
+            if !owner.contains_key(&reference) {
+                owner.insert(reference.clone(), id.clone());
+                registry.get_mut(&id).unwrap().1.insert(reference);
+            }
+        }
+        assigned.insert(id.clone());
+        m.plant_id = id;
+    }
+
+    let mut csv_writer = Writer::from_path(&registry_file).unwrap();
+    for (id, (name, references)) in registry {
+        let filter = |prefix| {
+            let values = references.iter().filter_map(|r: &String| r.strip_prefix(prefix));
+            values.collect::<Vec<_>>().join("|")
+        };
+        let (eics, ets_ids) = (filter("eic:"), filter("id:"));
+        csv_writer.serialize(RegistryEntry { id, name, eics, ets_ids }).unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
+
+fn split(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split('|').filter(|s| !s.is_empty()).map(str::to_string)
+}
@@ -8,12 +8,17 @@ use csv::{ReaderBuilder, Writer};
 use zip::ZipArchive;
 
 pub(crate) use crate::FilePaths;
-use crate::{YearlyEmission, YearlyGeneration};
+use crate::{
+    YearlyEmission, YearlyGeneration,
+    country_codes::{CountryCodes, Dataset, report_unmapped},
+    ets_parameters::EtsParameters,
+};
 
 pub(crate) fn yearly_emissions(year: u32, paths: &FilePaths) -> BTreeSet<String> {
     let mut excel: Xlsx<_> = calamine::open_workbook(paths.verified_emissions_file()).unwrap();
     let worksheets = excel.worksheets();
     let (_, sheet) = worksheets.first().unwrap();
+    let country_codes = CountryCodes::load(paths);
 
     let header_row = (0..100)
         .find(|&row| sheet.get((row, 0)).unwrap().get_string() == Some("REGISTRY_CODE"))
@@ -28,6 +33,9 @@ pub(crate) fn yearly_emissions(year: u32, paths: &FilePaths) -> BTreeSet<String>
     let allocations_2018_col = find_col(sheet, header_row, "ALLOCATION_2018");
     let allocations_2019_col = find_col(sheet, header_row, "ALLOCATION_2019");
 
+    let params_2018 = EtsParameters::load(paths, 2018);
+    let params_2019 = EtsParameters::load(paths, 2019);
+
     let mut countries = BTreeSet::new();
     let mut pp_emissions = Vec::new();
 
@@ -65,10 +73,8 @@ pub(crate) fn yearly_emissions(year: u32, paths: &FilePaths) -> BTreeSet<String>
                 if allocations_2018 <= 0.0 || allocations_2019 <= 0.0 {
                     0.0
                 } else {
-                    let beta_2018 = 1.0 - 0.0174 * f64::from(2018 - 2013);
-                    let beta_2019 = 1.0 - 0.0174 * f64::from(2019 - 2013);
-                    let gamma_2018 = 0.8 - (0.5 / 7.0) * f64::from(2018 - 2013);
-                    let gamma_2019 = 0.8 - (0.5 / 7.0) * f64::from(2019 - 2013);
+                    let EtsParameters { beta: beta_2018, gamma: gamma_2018, .. } = params_2018;
+                    let EtsParameters { beta: beta_2019, gamma: gamma_2019, .. } = params_2019;
                     let raw_sigma = (allocations_2018 * beta_2019 * gamma_2019
                         - allocations_2019 * beta_2018 * gamma_2018)
                         / (allocations_2019 * beta_2018 * (1.0 - gamma_2018)
@@ -79,9 +85,10 @@ pub(crate) fn yearly_emissions(year: u32, paths: &FilePaths) -> BTreeSet<String>
                 0.0
             };
 
-            countries.insert(country.to_string());
+            let country = country_codes.normalize(Dataset::Ets, country);
+            countries.insert(country.clone());
             pp_emissions.push(YearlyEmission {
-                country: country.to_string(),
+                country,
                 name: name.to_string(),
                 id: format!("{permit_id}:{installation_id}"),
                 emissions,
@@ -139,6 +146,8 @@ struct UnitData {
 
 pub(crate) fn yearly_generation(countries: &BTreeSet<String>, paths: &FilePaths) {
     let mut units = BTreeMap::<String, UnitData>::new();
+    let country_codes = CountryCodes::load(paths);
+    let mut unmapped_countries = BTreeSet::new();
 
     for month in 1..=12 {
         let zip_name = paths.entso_e_zip_file(month);
@@ -151,13 +160,10 @@ pub(crate) fn yearly_generation(countries: &BTreeSet<String>, paths: &FilePaths)
         for result in csv_reader.deserialize() {
             let generation_hour: UnitGenerationHour = result.unwrap();
 
-            let mut country = generation_hour.map_code.split('_').next().unwrap().to_string();
-            if country == "NIE" || country == "XI" {
-                // Power plants in Northern Ireland seem to vary between XI, IE or NIE in different data sources
-                // Set all of them to "IE" for matching to succeed
-                country = "IE".to_string();
-            }
+            let map_code = generation_hour.map_code.split('_').next().unwrap();
+            let country = country_codes.normalize(Dataset::Entsoe, map_code);
             if !countries.contains(&country) {
+                unmapped_countries.insert(country);
                 continue;
             }
 
@@ -204,6 +210,11 @@ pub(crate) fn yearly_generation(countries: &BTreeSet<String>, paths: &FilePaths)
         }
     }
 
+    report_unmapped(Dataset::Entsoe, &unmapped_countries);
+    let generation_countries: BTreeSet<_> =
+        units.values().map(|unit| unit.generation.country.clone()).collect();
+    report_unmapped(Dataset::Ets, &(countries - &generation_countries));
+
     let mut csv_writer = Writer::from_path(paths.generation_file()).unwrap();
     for (unit_eic, mut unit) in units {
         unit.generation.eic = unit_eic;
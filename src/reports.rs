@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    FilePaths, closest_names,
+    country_codes::{CountryCodes, Dataset},
+    generation_emission_match::{Match, YearlyEmission, YearlyGeneration, join_vec},
+    load_csv_file,
+    name_keys::StopWords,
+    total_generation,
+};
+
+#[derive(Serialize)]
+struct UnmatchedEmission<'a> {
+    country: &'a str,
+    id: &'a str,
+    name: &'a str,
+    emissions: f64,
+    #[serde(serialize_with = "join_vec")]
+    closest_generation: Vec<&'a str>,
+}
+
+#[derive(Default, Serialize)]
+struct EtsCoverage {
+    country: String,
+    total_emissions: f64,
+    matched_emissions: f64,
+    coverage_percentage: f64,
+}
+
+/// All ETS records of the year, with normalized country codes
+fn load_emissions(paths: &FilePaths) -> Vec<YearlyEmission> {
+    let mut emissions: Vec<YearlyEmission> =
+        load_csv_file(&paths.emissions_file(), ',').deserialize().map(Result::unwrap).collect();
+    let country_codes = CountryCodes::load(paths);
+    for em in &mut emissions {
+        em.country = country_codes.normalize(Dataset::Ets, &em.country);
+    }
+    emissions
+}
+
+/// Writes ETS records that no generation unit was matched to, largest emissions first.
+pub(crate) fn unmatched_emissions(matches: &[Match], paths: &FilePaths) {
+    let matched_ids: BTreeSet<_> = matches
+        .iter()
+        .filter(|m| !m.is_ignored())
+        .flat_map(|m| &m.emission)
+        .map(|e| e.id.as_str())
+        .collect();
+
+    let stop_words = StopWords::load(paths);
+    let mut generation_keys = BTreeMap::<String, Vec<(String, String)>>::new();
+    let mut csv_reader = load_csv_file(&paths.generation_file(), ',');
+    for result in csv_reader.deserialize() {
+        let csv_gen: YearlyGeneration = result.unwrap();
+        let key = stop_words.key(&csv_gen.country, &csv_gen.name);
+        generation_keys.entry(csv_gen.country).or_default().push((key, csv_gen.name));
+    }
+
+    let mut emissions = load_emissions(paths);
+    emissions.sort_by(|x, y| y.emissions.total_cmp(&x.emissions));
+
+    let mut csv_writer = Writer::from_path(paths.out_unmatched_emissions_file()).unwrap();
+    for em in &emissions {
+        if matched_ids.contains(em.id.as_str()) || em.emissions == 0.0 {
+            continue;
+        }
+
+        let generation = generation_keys.get(&em.country).map(Vec::as_slice).unwrap_or_default();
+        let key = stop_words.key(&em.country, &em.name);
+        let similar_keys = closest_names(&key, generation.iter().map(|(k, _)| k.as_str()), 3);
+        let closest_generation = similar_keys
+            .iter()
+            .flat_map(|key| generation.iter().filter(move |(k, _)| k == key))
+            .map(|(_, name)| name.as_str())
+            .collect();
+
+        csv_writer
+            .serialize(UnmatchedEmission {
+                country: &em.country,
+                id: &em.id,
+                name: &em.name,
+                emissions: em.emissions,
+                closest_generation,
+            })
+            .unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
+
+/// Writes the share of ETS emissions per country that is covered by the matches included in the
+/// output. Matches only count with their share of the ETS emissions.
+pub(crate) fn ets_coverage(matches: &[Match], paths: &FilePaths) {
+    // emissions of the matches are already reduced to their emission share
+    let mut matched_emissions = BTreeMap::<&str, f64>::new();
+    for e in matches.iter().filter(|m| !m.is_ignored()).flat_map(|m| &m.emission) {
+        *matched_emissions.entry(e.id.as_str()).or_default() += e.emissions;
+    }
+
+    let mut coverage = BTreeMap::<String, EtsCoverage>::new();
+    for em in load_emissions(paths) {
+        let matched = matched_emissions.get(em.id.as_str()).copied().unwrap_or_default();
+        for country in [em.country.as_str(), ""] {
+            let stats = coverage.entry(country.to_string()).or_default();
+            stats.total_emissions += em.emissions;
+            stats.matched_emissions += matched.min(em.emissions);
+        }
+    }
+
+    let mut csv_writer = Writer::from_path(paths.out_ets_coverage_file()).unwrap();
+    for (country, mut stats) in coverage {
+        stats.country = country;
+        stats.coverage_percentage = if stats.total_emissions > 0.0 {
+            (100.0 * stats.matched_emissions) / stats.total_emissions
+        } else {
+            100.0
+        };
+        csv_writer.serialize(stats).unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
+
+#[derive(Serialize)]
+struct WorklistEntry<'a> {
+    coverage_rank: usize,
+    generation_rank: usize,
+    country: &'a str,
+    fuel: &'a str,
+    name: &'a str,
+    #[serde(serialize_with = "join_vec")]
+    generation: &'a [YearlyGeneration],
+    ignore_reason: &'a str,
+    generation_el: f64,
+    coverage_gain_percentage: f64,
+}
+
+/// Writes ignored matches ranked by how much matching them would increase the coverage of their
+/// country and fuel, so matching effort can go to the plants that matter most.
+pub(crate) fn worklist(matches: &[Match], paths: &FilePaths) {
+    let mut total_generation = total_generation(matches, paths);
+    let mut mixed_generation = BTreeMap::<_, f64>::new();
+    for ((country, _), generation) in &total_generation {
+        *mixed_generation.entry((country.clone(), "mixed".to_string())).or_default() += generation;
+    }
+    total_generation.append(&mut mixed_generation);
+
+    let mut entries: Vec<_> = matches
+        .iter()
+        .filter(|m| m.is_ignored() && m.generation_el > 0.0)
+        .map(|m| {
+            // mixed fuel plants are compared to the country's total generation of all fuels
+            let fuel = m.fuel.as_deref().unwrap_or("mixed");
+            let total = total_generation.get(&(m.country.clone(), fuel.to_string()));
+            WorklistEntry {
+                coverage_rank: 0,
+                generation_rank: 0,
+                country: &m.country,
+                fuel,
+                name: &m.name,
+                generation: &m.generation,
+                ignore_reason: m.ignore_reason().unwrap_or_default(),
+                generation_el: m.generation_el,
+                coverage_gain_percentage: 100.0 * m.generation_el / total.unwrap_or(&f64::INFINITY),
+            }
+        })
+        .collect();
+
+    entries.sort_by(|x, y| y.generation_el.total_cmp(&x.generation_el));
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.generation_rank = i + 1;
+    }
+
+    entries.sort_by(|x, y| y.coverage_gain_percentage.total_cmp(&x.coverage_gain_percentage));
+    let mut csv_writer = Writer::from_path(paths.out_worklist_file()).unwrap();
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        entry.coverage_rank = i + 1;
+        csv_writer.serialize(entry).unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
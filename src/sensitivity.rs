@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    CalculationOptions, FilePaths, FuelStats, HeatInputs, calculate_emission_factors,
+    country_stats,
+    ets_parameters::EtsParameters,
+    filter_matches, generate_auto_matches,
+    generation_emission_match::{Match, dominant_fuel},
+    load_degree_days, load_manual_matches, opsd,
+};
+
+/// A change of one assumption of the emission factor calculation
+#[derive(Clone, Copy)]
+pub(crate) enum Perturbation {
+    /// relative change of the heat benchmark
+    HeatBenchmark(f64),
+    /// relative change of the assumed efficiency of heat generation
+    EfficiencyHeat(f64),
+    /// relative change of the assumed efficiency of electricity generation
+    EfficiencyEl(f64),
+    /// absolute change of sigma (the share of heat allocations that are privileged)
+    Sigma(f64),
+    /// the baseline period of the degree days is shifted by this number of years
+    BaselineShift(i32),
+    /// share of the output that one fuel needs to provide for a plant to be attributed to it
+    FuelShareThreshold(f64),
+}
+
+/// Each perturbation is applied on its own, with all other assumptions at their defaults
+const PERTURBATIONS: [Perturbation; 12] = [
+    Perturbation::HeatBenchmark(-0.1),
+    Perturbation::HeatBenchmark(0.1),
+    Perturbation::EfficiencyHeat(-0.1),
+    Perturbation::EfficiencyHeat(0.1),
+    Perturbation::EfficiencyEl(-0.1),
+    Perturbation::EfficiencyEl(0.1),
+    Perturbation::Sigma(-0.1),
+    Perturbation::Sigma(0.1),
+    Perturbation::BaselineShift(-1),
+    Perturbation::BaselineShift(1),
+    Perturbation::FuelShareThreshold(0.9),
+    Perturbation::FuelShareThreshold(0.99),
+];
+
+impl Perturbation {
+    fn parameter(self) -> &'static str {
+        match self {
+            Self::HeatBenchmark(_) => "heat_benchmark",
+            Self::EfficiencyHeat(_) => "efficiency_heat",
+            Self::EfficiencyEl(_) => "efficiency_el",
+            Self::Sigma(_) => "sigma",
+            Self::BaselineShift(_) => "degree_days_baseline",
+            Self::FuelShareThreshold(_) => "fuel_share_threshold",
+        }
+    }
+
+    fn change(self) -> String {
+        match self {
+            Self::HeatBenchmark(change)
+            | Self::EfficiencyHeat(change)
+            | Self::EfficiencyEl(change) => {
+                format!("{:+.0}%", 100.0 * change)
+            }
+            Self::Sigma(change) => format!("{change:+}"),
+            Self::BaselineShift(years) => format!("{years:+} years"),
+            Self::FuelShareThreshold(threshold) => format!("{threshold}"),
+        }
+    }
+
+    pub(crate) fn apply_to_parameters(self, params: &mut EtsParameters) {
+        match self {
+            Self::HeatBenchmark(change) => params.heat_benchmark *= 1.0 + change,
+            Self::BaselineShift(years) => {
+                let shift = |year: u32| year.checked_add_signed(years).unwrap();
+                params.baseline = shift(*params.baseline.start())..=shift(*params.baseline.end());
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn apply_to_inputs(self, inputs: &mut HeatInputs) {
+        match self {
+            Self::EfficiencyHeat(change) => {
+                inputs.efficiency_heat = (inputs.efficiency_heat * (1.0 + change)).min(1.0);
+            }
+            Self::EfficiencyEl(change) => {
+                inputs.efficiency_el = (inputs.efficiency_el * (1.0 + change)).min(1.0);
+            }
+            Self::Sigma(change) => inputs.sigma = (inputs.sigma + change).clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    /// The fuel share threshold is used when matching, so it is applied to the matches before the
+    /// calculation. Fuels set manually stay as they are.
+    fn apply_to_matches(self, matches: &mut [Match]) {
+        if let Self::FuelShareThreshold(threshold) = self {
+            for m in matches.iter_mut() {
+                if !m.has_manual_fuel() {
+                    m.fuel = dominant_fuel(&m.generation, threshold);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SensitivityRow<'a> {
+    country: &'a str,
+    fuel: &'a str,
+    parameter: &'static str,
+    change: String,
+    emission_factor: Option<f64>,
+    perturbed_emission_factor: Option<f64>,
+    change_percentage: Option<f64>,
+}
+
+/// Calculates the country-level emission factors once with the default assumptions and once per
+/// perturbation, and writes how much each perturbation changes them to "output/sensitivity.csv"
+pub(crate) fn sensitivity(year: u32, paths: &FilePaths) {
+    let mut matches = Vec::new();
+    load_manual_matches(&mut matches, paths);
+    opsd::load_external_matches(&mut matches, paths);
+    generate_auto_matches(&mut matches, false, paths);
+    let degdays = load_degree_days(year, paths);
+
+    let calculate = |perturbation: Option<Perturbation>| -> BTreeMap<_, FuelStats> {
+        let mut matches = matches.clone();
+        if let Some(perturbation) = perturbation {
+            perturbation.apply_to_matches(&mut matches);
+        }
+        filter_matches(&mut matches);
+
+        let options = CalculationOptions { perturbation, ..Default::default() };
+        calculate_emission_factors(year, &options, &degdays, &mut matches, paths);
+        country_stats(&matches, paths)
+    };
+
+    let reference = calculate(None);
+    let mut csv_writer = Writer::from_path(paths.out_sensitivity_file()).unwrap();
+    for perturbation in PERTURBATIONS {
+        let perturbed = calculate(Some(perturbation));
+        for ((country, fuel), stat) in &reference {
+            let perturbed_emission_factor =
+                perturbed.get(&(country.clone(), fuel.clone())).and_then(|s| s.emission_factor);
+            let change_percentage = stat
+                .emission_factor
+                .zip(perturbed_emission_factor)
+                .map(|(ef, perturbed_ef)| 100.0 * (perturbed_ef / ef - 1.0));
+
+            csv_writer
+                .serialize(SensitivityRow {
+                    country,
+                    fuel,
+                    parameter: perturbation.parameter(),
+                    change: perturbation.change(),
+                    emission_factor: stat.emission_factor,
+                    perturbed_emission_factor,
+                    change_percentage,
+                })
+                .unwrap();
+        }
+    }
+    csv_writer.flush().unwrap();
+}
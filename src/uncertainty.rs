@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{FilePaths, HeatInputs, load_csv_file};
+
+/// Same seed for every run, so results are reproducible
+const SEED: u64 = 0x5eed_2024;
+/// Sampled efficiencies are divided by, so they are kept above this
+const MIN_EFFICIENCY: f64 = 0.01;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Distribution {
+    /// `deviation` is the standard deviation
+    Normal,
+    /// `deviation` is half the width of the interval
+    Uniform,
+}
+
+#[derive(Deserialize)]
+struct UncertaintyRow {
+    parameter: String,
+    distribution: Distribution,
+    deviation: f64,
+}
+
+/// Distributions of the uncertain inputs of the heat estimate, from "data/uncertainty.csv". All
+/// distributions are centered on the point value. Deviations of sigma are absolute, the others are
+/// relative to the point value.
+pub(crate) struct Uncertainty {
+    pub(crate) runs: usize,
+    distributions: BTreeMap<String, (Distribution, f64)>,
+}
+
+impl Uncertainty {
+    pub(crate) fn load(paths: &FilePaths, runs: usize) -> Self {
+        let mut distributions = BTreeMap::new();
+        for result in load_csv_file(&paths.uncertainty_file(), ',').deserialize() {
+            let row: UncertaintyRow = result.unwrap();
+            match row.parameter.as_str() {
+                "sigma" | "degree_days" | "efficiency_heat" | "efficiency_el" => {
+                    distributions.insert(row.parameter, (row.distribution, row.deviation));
+                }
+                _ => panic!("unknown parameter {} in uncertainty.csv", row.parameter),
+            }
+        }
+
+        Self { runs, distributions }
+    }
+
+    fn deviation(&self, parameter: &str, rng: &mut Rng) -> f64 {
+        match self.distributions.get(parameter) {
+            Some((Distribution::Normal, sd)) => rng.normal() * sd,
+            Some((Distribution::Uniform, half_width)) => (rng.uniform() * 2.0 - 1.0) * half_width,
+            None => 0.0,
+        }
+    }
+
+    /// Draws one sample of the inputs around their point values
+    pub(crate) fn sample(&self, inputs: &HeatInputs, rng: &mut Rng) -> HeatInputs {
+        let relative = |value: f64, parameter, rng: &mut Rng| {
+            value * (1.0 + self.deviation(parameter, rng)).max(0.0)
+        };
+
+        HeatInputs {
+            sigma: (inputs.sigma + self.deviation("sigma", rng)).clamp(0.0, 1.0),
+            degdays_ratio: relative(inputs.degdays_ratio, "degree_days", rng),
+            efficiency_heat: relative(inputs.efficiency_heat, "efficiency_heat", rng)
+                .clamp(MIN_EFFICIENCY, 1.0),
+            efficiency_el: relative(inputs.efficiency_el, "efficiency_el", rng)
+                .clamp(MIN_EFFICIENCY, 1.0),
+        }
+    }
+}
+
+/// SplitMix64, good enough for sampling and without another dependency
+pub(crate) struct Rng(u64);
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self(SEED)
+    }
+}
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal distribution (Box-Muller transform)
+    fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+/// 5th, 50th and 95th percentile (nearest rank) of the given samples
+pub(crate) fn percentiles(mut samples: Vec<f64>) -> (f64, f64, f64) {
+    samples.sort_by(f64::total_cmp);
+    let rank = |p: f64| samples[((p * samples.len() as f64).ceil() as usize).max(1) - 1];
+    (rank(0.05), rank(0.5), rank(0.95))
+}